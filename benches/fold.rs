@@ -0,0 +1,74 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use oxidized_patterns::patterns::fold::arena::{Arena, ArenaFolder};
+use oxidized_patterns::patterns::fold::ast::{Expr, ExprKind, Name, Stmt, StmtKind};
+use oxidized_patterns::patterns::fold::Folder;
+
+const DEPTH: u32 = 16;
+const ROUNDS: u32 = 50;
+
+struct BoxedIdentity;
+
+impl Folder for BoxedIdentity {}
+
+struct ArenaIdentity;
+
+impl ArenaFolder for ArenaIdentity {}
+
+fn generate(depth: u32, seed: &mut u64) -> Box<Expr> {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    let kind = if depth == 0 {
+        match *seed % 3 {
+            0 => ExprKind::Var(Box::new(Name::new(format!("v{}", *seed % 8)))),
+            _ => ExprKind::IntLit((*seed % 100) as i64),
+        }
+    } else if seed.is_multiple_of(2) {
+        ExprKind::Add(generate(depth - 1, seed), generate(depth - 1, seed))
+    } else {
+        ExprKind::Sub(generate(depth - 1, seed), generate(depth - 1, seed))
+    };
+    Box::new(Expr::new(kind))
+}
+
+fn measure(label: &str, mut run: impl FnMut() -> usize) -> Duration {
+    let start = Instant::now();
+    let mut checksum = 0usize;
+    for _ in 0..ROUNDS {
+        checksum = checksum.wrapping_add(run());
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<16} {:>10.2?} total  {:>10.2?}/fold  (checksum {})",
+        label,
+        elapsed,
+        elapsed / ROUNDS,
+        checksum
+    );
+    elapsed
+}
+
+fn main() {
+    let stmt = Box::new(Stmt::new(StmtKind::Let(
+        Box::new(Name::new("x")),
+        generate(DEPTH, &mut 0x2545_f491_4f6c_dd1d),
+    )));
+    let mut arena = Arena::new();
+    let root = arena.import_stmt(&stmt);
+    println!("nodes: {}", arena.len());
+
+    let mut current = Some(stmt);
+    let boxed = measure("boxed identity", || {
+        let folded = BoxedIdentity.fold_stmt(black_box(current.take().unwrap()));
+        let size = matches!(folded.kind, StmtKind::Let(..)) as usize;
+        current = Some(folded);
+        size
+    });
+    let shared = measure("arena identity", || {
+        let folded = ArenaIdentity.fold_stmt(&mut arena, black_box(root));
+        arena.len() + (folded == root) as usize
+    });
+    println!("speedup: {:.2}x", boxed.as_secs_f64() / shared.as_secs_f64());
+}
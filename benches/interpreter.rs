@@ -0,0 +1,44 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use oxidized_patterns::patterns::interpreter::bytecode::{compile, Vm};
+use oxidized_patterns::patterns::interpreter::parser::parse;
+use oxidized_patterns::patterns::interpreter::Environment;
+
+const FORMULA: &str = "let d = (x - 7) * (x + 3) in (d * d - 4 * x) / (x % 11 + 12) + -(d % 5)";
+const INPUTS: i32 = 1_000_000;
+
+fn measure(label: &str, mut run: impl FnMut(i32) -> i32) -> Duration {
+    let start = Instant::now();
+    let mut checksum = 0i32;
+    for x in 0..INPUTS {
+        checksum = checksum.wrapping_add(run(black_box(x % 100)));
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<12} {:>10.2?} total  {:>8.1} ns/eval  (checksum {})",
+        label,
+        elapsed,
+        elapsed.as_nanos() as f64 / INPUTS as f64,
+        checksum
+    );
+    elapsed
+}
+
+fn main() {
+    let expr = parse(FORMULA).expect("benchmark formula should parse");
+    let program = compile(expr.as_ref()).expect("benchmark formula should compile");
+    let mut vm = Vm::new();
+    let mut env = Environment::new();
+
+    println!("formula: {}", FORMULA);
+    let tree = measure("tree-walk", |x| {
+        env.set("x", x);
+        expr.evaluate(&env).unwrap()
+    });
+    let bytecode = measure("bytecode", |x| {
+        env.set("x", x);
+        vm.run(&program, &env).unwrap()
+    });
+    println!("speedup: {:.2}x", tree.as_secs_f64() / bytecode.as_secs_f64());
+}
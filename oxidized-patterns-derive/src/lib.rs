@@ -0,0 +1,298 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Ident, Index, Result, Type,
+    WhereClause,
+};
+
+#[proc_macro_derive(Element, attributes(element))]
+pub fn derive_element(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_element(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(Walk, attributes(walk))]
+pub fn derive_walk(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_walk(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_element(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let visit = element_visit_method(&input.attrs)?;
+    let mut wrapped = Vec::new();
+    let mut children = None;
+    let (body, output_body) = match &input.data {
+        Data::Struct(_) => {
+            let method = visit.unwrap_or_else(|| {
+                format_ident!("visit_{}", snake_case(&name.to_string()), span = name.span())
+            });
+            (quote! { visitor.#method(self); }, quote! { visitor.#method(self) })
+        }
+        Data::Enum(data) => {
+            if let Some(method) = visit {
+                return Err(Error::new(
+                    method.span(),
+                    "`#[element(visit = ...)]` is only supported on structs; \
+                     enums dispatch to the element each variant wraps",
+                ));
+            }
+            let mut arms = Vec::new();
+            let mut output_arms = Vec::new();
+            let mut children_arms = Vec::new();
+            for variant in &data.variants {
+                let ident = &variant.ident;
+                match &variant.fields {
+                    Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                        wrapped.push(fields.unnamed[0].ty.clone());
+                        arms.push(quote! {
+                            Self::#ident(inner) => {
+                                ::oxidized_patterns::patterns::visitor::Element::accept(
+                                    inner, visitor,
+                                )
+                            }
+                        });
+                        output_arms.push(quote! {
+                            Self::#ident(inner) => {
+                                ::oxidized_patterns::patterns::visitor::Accept::accept_with(
+                                    inner, visitor,
+                                )
+                            }
+                        });
+                        children_arms.push(quote! {
+                            Self::#ident(inner) => {
+                                ::oxidized_patterns::patterns::visitor::Element::children(inner)
+                            }
+                        });
+                    }
+                    _ => {
+                        return Err(Error::new(
+                            variant.span(),
+                            "`Element` can only be derived for enums whose variants wrap \
+                             exactly one element, like `Variant(Inner)`",
+                        ));
+                    }
+                }
+            }
+            if arms.is_empty() {
+                children = Some(quote! { match *self {} });
+                (quote! { match *self {} }, quote! { match *self {} })
+            } else {
+                children = Some(quote! { match self { #(#children_arms)* } });
+                (quote! { match self { #(#arms)* } }, quote! { match self { #(#output_arms)* } })
+            }
+        }
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span,
+                "`Element` cannot be derived for unions",
+            ));
+        }
+    };
+
+    let children = children.map(|body| {
+        quote! {
+            fn children(
+                &self,
+            ) -> &[::std::boxed::Box<dyn ::oxidized_patterns::patterns::visitor::Element>] {
+                #body
+            }
+        }
+    });
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let visitor = Ident::new("__V", Span::call_site());
+    let mut generics = input.generics.clone();
+    generics.params.push(syn::parse_quote!(
+        #visitor: ::oxidized_patterns::patterns::visitor::OutputVisitor + ?Sized
+    ));
+    let (output_impl_generics, _, _) = generics.split_for_impl();
+    let predicates = where_predicates(where_clause, wrapped, |ty| {
+        quote!(#ty: ::oxidized_patterns::patterns::visitor::Accept<#visitor>)
+    });
+    Ok(quote! {
+        impl #impl_generics ::oxidized_patterns::patterns::visitor::Element
+            for #name #ty_generics #where_clause
+        {
+            fn accept(
+                &self,
+                visitor: &mut dyn ::oxidized_patterns::patterns::visitor::Visitor,
+            ) {
+                #body
+            }
+
+            #children
+        }
+
+        impl #output_impl_generics ::oxidized_patterns::patterns::visitor::Accept<#visitor>
+            for #name #ty_generics
+        where
+            #(#predicates,)*
+        {
+            fn accept_with(&self, visitor: &mut #visitor) -> #visitor::Output {
+                #output_body
+            }
+        }
+    })
+}
+
+fn where_predicates(
+    where_clause: Option<&WhereClause>,
+    bounded: Vec<Type>,
+    bound: impl Fn(&Type) -> TokenStream2,
+) -> Vec<TokenStream2> {
+    let mut predicates: Vec<TokenStream2> = where_clause
+        .map(|w| w.predicates.iter().map(|p| quote!(#p)).collect())
+        .unwrap_or_default();
+    let mut seen = Vec::new();
+    for ty in bounded {
+        let key = quote!(#ty).to_string();
+        if !seen.contains(&key) {
+            seen.push(key);
+            predicates.push(bound(&ty));
+        }
+    }
+    predicates
+}
+
+fn element_visit_method(attrs: &[Attribute]) -> Result<Option<Ident>> {
+    let mut method = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("element")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("visit") {
+                method = Some(meta.value()?.parse::<Ident>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown `element` attribute, expected `visit = method_name`"))
+            }
+        })?;
+    }
+    Ok(method)
+}
+
+fn expand_walk(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let mut bounds = Vec::new();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, rebuild) = walk_fields(quote!(Self), &data.fields, &mut bounds)?;
+            quote! {
+                let #pattern = self;
+                #rebuild
+            }
+        }
+        Data::Enum(data) => {
+            let arms = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let ident = &variant.ident;
+                    let (pattern, rebuild) =
+                        walk_fields(quote!(Self::#ident), &variant.fields, &mut bounds)?;
+                    Ok(quote! { #pattern => #rebuild, })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            quote! { match self { #(#arms)* } }
+        }
+        Data::Union(data) => {
+            return Err(Error::new(data.union_token.span, "`Walk` cannot be derived for unions"));
+        }
+    };
+
+    let folder = Ident::new("__F", Span::call_site());
+    let mut generics = input.generics.clone();
+    generics.params.push(syn::parse_quote!(#folder: ?Sized));
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+    let predicates = where_predicates(where_clause, bounds, |ty| {
+        quote!(#ty: ::oxidized_patterns::patterns::fold::Walk<#folder>)
+    });
+    Ok(quote! {
+        impl #impl_generics ::oxidized_patterns::patterns::fold::Walk<#folder>
+            for #name #ty_generics
+        where
+            #(#predicates,)*
+        {
+            #[allow(unused_variables)]
+            fn walk(self, folder: &mut #folder) -> Self {
+                #body
+            }
+        }
+    })
+}
+
+fn walk_fields(
+    path: TokenStream2,
+    fields: &Fields,
+    bounds: &mut Vec<Type>,
+) -> Result<(TokenStream2, TokenStream2)> {
+    let mut bindings = Vec::new();
+    let mut values = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let binding = format_ident!("__field{}", i);
+        values.push(if walk_skipped(&field.attrs)? {
+            quote!(#binding)
+        } else {
+            bounds.push(field.ty.clone());
+            quote!(::oxidized_patterns::patterns::fold::Walk::walk(#binding, folder))
+        });
+        bindings.push(binding);
+    }
+    Ok(match fields {
+        Fields::Named(named) => {
+            let names: Vec<_> = named.named.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+            (
+                quote!(#path { #(#names: #bindings),* }),
+                quote!(#path { #(#names: #values),* }),
+            )
+        }
+        Fields::Unnamed(_) => {
+            let indices = (0..bindings.len()).map(Index::from);
+            (
+                quote!(#path { #(#indices: #bindings),* }),
+                quote!(#path(#(#values),*)),
+            )
+        }
+        Fields::Unit => (quote!(#path), quote!(#path)),
+    })
+}
+
+fn walk_skipped(attrs: &[Attribute]) -> Result<bool> {
+    let mut skip = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("walk")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown `walk` attribute, expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+// A run of capitals is one word, so `HTTPServer` becomes `http_server`: a
+// boundary falls before a capital that follows a lowercase letter or digit,
+// and before the last capital of a run when a lowercase letter follows it.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let prev = i.checked_sub(1).map(|j| chars[j]);
+            let next = chars.get(i + 1);
+            let after_word = prev.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit());
+            let ends_run = prev.is_some_and(char::is_uppercase)
+                && next.is_some_and(|n| n.is_lowercase());
+            if after_word || ends_run {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
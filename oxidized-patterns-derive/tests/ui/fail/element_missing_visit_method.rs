@@ -0,0 +1,8 @@
+use oxidized_patterns::patterns::visitor::Element;
+
+#[derive(Element)]
+struct Widget {
+    id: u32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use oxidized_patterns::patterns::visitor::Element;
+
+#[derive(Element)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
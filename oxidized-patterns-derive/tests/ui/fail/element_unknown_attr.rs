@@ -0,0 +1,9 @@
+use oxidized_patterns::patterns::visitor::Element;
+
+#[derive(Element)]
+#[element(method = visit_element_a)]
+struct Point {
+    x: i32,
+}
+
+fn main() {}
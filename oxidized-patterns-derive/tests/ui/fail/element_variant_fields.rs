@@ -0,0 +1,10 @@
+use oxidized_patterns::patterns::visitor::{Element, ElementA};
+
+#[derive(Element)]
+enum Shape {
+    Point(ElementA),
+    Pair(ElementA, ElementA),
+    Empty,
+}
+
+fn main() {}
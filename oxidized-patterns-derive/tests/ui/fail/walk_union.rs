@@ -0,0 +1,9 @@
+use oxidized_patterns::patterns::fold::Walk;
+
+#[derive(Walk)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use oxidized_patterns::patterns::fold::Walk;
+
+#[derive(Walk)]
+struct Node {
+    #[walk(ignore)]
+    value: i64,
+}
+
+fn main() {}
@@ -0,0 +1,38 @@
+use oxidized_patterns::patterns::visitor::{
+    Accept, ConcreteVisitor, Element, ElementA, OutputVisitor, Renderer, Visitor,
+};
+
+// The derive calls `visit_http_server`; no visitor in the crate knows this
+// type, so extension traits forward it to `visit_element_a`.
+#[derive(Element)]
+struct HTTPServer {
+    port: i32,
+}
+
+trait VisitHttpServer {
+    fn visit_http_server(&mut self, server: &HTTPServer);
+}
+
+impl<V: Visitor + ?Sized> VisitHttpServer for V {
+    fn visit_http_server(&mut self, server: &HTTPServer) {
+        self.visit_element_a(&ElementA { value: server.port });
+    }
+}
+
+trait RenderHttpServer: OutputVisitor {
+    fn visit_http_server(&mut self, server: &HTTPServer) -> Self::Output;
+}
+
+impl<V: OutputVisitor + ?Sized> RenderHttpServer for V {
+    fn visit_http_server(&mut self, server: &HTTPServer) -> V::Output {
+        self.visit_element_a(&ElementA { value: server.port })
+    }
+}
+
+fn main() {
+    let server = HTTPServer { port: 8080 };
+    let mut visitor = ConcreteVisitor::new();
+    server.accept(&mut visitor);
+    assert_eq!(visitor.sum, 8080);
+    assert_eq!(server.accept_with(&mut Renderer), "A(8080)");
+}
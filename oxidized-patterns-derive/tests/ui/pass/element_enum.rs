@@ -0,0 +1,38 @@
+use oxidized_patterns::patterns::visitor::{
+    visit_all, Accept, ConcreteVisitor, Element, ElementA, ElementB, Renderer,
+};
+
+#[derive(Element)]
+enum Shape {
+    Point(ElementA),
+    Label(ElementB),
+}
+
+#[derive(Element)]
+enum Either<L: Element, R>
+where
+    R: Element,
+{
+    Left(L),
+    Right(R),
+}
+
+#[derive(Element)]
+enum Never {}
+
+fn main() {
+    let shapes: Vec<Either<Shape, ElementA>> = vec![
+        Either::Left(Shape::Point(ElementA { value: 2 })),
+        Either::Left(Shape::Label(ElementB { text: "x".into() })),
+        Either::Right(ElementA { value: 3 }),
+    ];
+    let mut visitor = ConcreteVisitor::new();
+    for shape in &shapes {
+        shape.accept(&mut visitor);
+    }
+    assert_eq!(visitor.sum, 5);
+    assert_eq!(visitor.texts, vec!["x".to_string()]);
+    assert_eq!(visit_all(&shapes, &mut Renderer), ["A(2)", "B(\"x\")", "A(3)"]);
+    let _: Option<&dyn Accept<Renderer>> = None::<&Never>.map(|n| n as &dyn Accept<Renderer>);
+    let _: Option<&dyn Element> = None::<&Never>.map(|n| n as &dyn Element);
+}
@@ -0,0 +1,69 @@
+use oxidized_patterns::patterns::fold::ast::{Stmt, StmtKind};
+use oxidized_patterns::patterns::fold::parser::parse_stmts;
+use oxidized_patterns::patterns::fold::{Folder, Renamer, Walk};
+
+#[derive(Debug, PartialEq, Walk)]
+enum Expr {
+    Lit(i64),
+    Neg(Box<Expr>),
+    Add { left: Box<Expr>, right: Box<Expr> },
+    Call(String, Vec<Box<Expr>>),
+    Hole,
+}
+
+trait ExprFolder {
+    fn fold_expr(&mut self, e: Box<Expr>) -> Box<Expr> {
+        Box::new((*e).walk(self))
+    }
+}
+
+impl<F: ExprFolder + ?Sized> Walk<F> for Box<Expr> {
+    fn walk(self, folder: &mut F) -> Self {
+        folder.fold_expr(self)
+    }
+}
+
+struct Doubler;
+
+impl ExprFolder for Doubler {
+    fn fold_expr(&mut self, e: Box<Expr>) -> Box<Expr> {
+        match *e {
+            Expr::Lit(v) => Box::new(Expr::Lit(v * 2)),
+            other => Box::new(other.walk(self)),
+        }
+    }
+}
+
+struct Opaque;
+
+#[derive(Walk)]
+struct Module<T> {
+    name: String,
+    body: Vec<Box<Stmt>>,
+    #[walk(skip)]
+    extra: T,
+}
+
+fn main() {
+    let e = Expr::Add {
+        left: Box::new(Expr::Neg(Box::new(Expr::Lit(1)))),
+        right: Box::new(Expr::Call("f".into(), vec![Box::new(Expr::Lit(2)), Box::new(Expr::Hole)])),
+    };
+    let doubled = e.walk(&mut Doubler);
+    let expected = Expr::Add {
+        left: Box::new(Expr::Neg(Box::new(Expr::Lit(2)))),
+        right: Box::new(Expr::Call("f".into(), vec![Box::new(Expr::Lit(4)), Box::new(Expr::Hole)])),
+    };
+    assert_eq!(doubled, expected);
+
+    let module = Module {
+        name: "m".into(),
+        body: parse_stmts("let x = y;").unwrap(),
+        extra: Opaque,
+    };
+    let renamed = module.walk(&mut Renamer);
+    let Opaque = renamed.extra;
+    assert_eq!(renamed.name, "m");
+    assert!(matches!(&renamed.body[0].kind, StmtKind::Let(n, _) if n.value == "foo"));
+    let _ = Renamer.fold_stmt(renamed.body.into_iter().next().unwrap());
+}
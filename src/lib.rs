@@ -1 +1,3 @@
-pub mod patterns;
\ No newline at end of file
+extern crate self as oxidized_patterns;
+
+pub mod patterns;
@@ -20,6 +20,12 @@ impl Builder<Missing, Missing> {
     }
 }
 
+impl Default for Builder<Missing, Missing> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<Q> Builder<Missing, Q> {
     pub fn name(self, name: impl Into<String>) -> Builder<Set, Q> {
         Builder { name: Some(name.into()), quantity: self.quantity, _marker: PhantomData }
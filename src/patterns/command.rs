@@ -1,36 +1,123 @@
+pub mod journal;
+pub mod queue;
+
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entry(u64);
+
+#[derive(Default)]
+struct Log { entries: Vec<(Entry, String)>, next: u64 }
 
 #[derive(Clone)]
-pub struct Receiver { log: Rc<RefCell<Vec<String>>> }
+pub struct Receiver { log: Rc<RefCell<Log>> }
 
 impl Receiver {
     pub fn new() -> Self {
-        Receiver { log: Rc::new(RefCell::new(Vec::new())) }
+        Receiver { log: Rc::new(RefCell::new(Log::default())) }
     }
-    pub fn action(&self, info: &str) {
-        self.log.borrow_mut().push(info.to_string());
+    pub fn action(&self, info: &str) -> Entry {
+        let mut log = self.log.borrow_mut();
+        let entry = Entry(log.next);
+        log.next += 1;
+        log.entries.push((entry, info.to_string()));
+        entry
+    }
+    pub fn revert(&self, entry: Entry) -> bool {
+        let mut log = self.log.borrow_mut();
+        match log.entries.iter().position(|(e, _)| *e == entry) {
+            Some(pos) => {
+                log.entries.remove(pos);
+                true
+            }
+            None => false,
+        }
     }
     pub fn get_log(&self) -> Vec<String> {
-        self.log.borrow().clone()
+        self.log.borrow().entries.iter().map(|(_, info)| info.clone()).collect()
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Failed(String),
+    RolledBack { index: usize, cause: Box<CommandError> },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Failed(reason) => write!(f, "command failed: {}", reason),
+            CommandError::RolledBack { index, cause } => {
+                write!(f, "step {} failed and the macro was rolled back: {}", index, cause)
+            }
+        }
     }
 }
 
+impl Error for CommandError {}
+
 pub trait Command {
     fn execute(&self);
+
+    fn try_execute(&self) -> Result<(), CommandError> {
+        self.execute();
+        Ok(())
+    }
 }
 
-pub struct ConcreteCommand { receiver: Receiver, info: String }
+pub trait UndoableCommand: Command {
+    fn undo(&self);
+}
+
+impl<C: Command + ?Sized> Command for Box<C> {
+    fn execute(&self) {
+        (**self).execute();
+    }
+
+    fn try_execute(&self) -> Result<(), CommandError> {
+        (**self).try_execute()
+    }
+}
+
+impl<C: UndoableCommand + ?Sized> UndoableCommand for Box<C> {
+    fn undo(&self) {
+        (**self).undo();
+    }
+}
+
+// Each execution remembers the entry it added, so undo removes exactly that entry even when
+// other commands have logged the same text since.
+pub struct ConcreteCommand { receiver: Receiver, info: String, entries: RefCell<Vec<Entry>> }
 
 impl ConcreteCommand {
     pub fn new(receiver: Receiver, info: impl Into<String>) -> Self {
-        ConcreteCommand { receiver, info: info.into() }
+        ConcreteCommand { receiver, info: info.into(), entries: RefCell::new(Vec::new()) }
     }
 }
 
 impl Command for ConcreteCommand {
     fn execute(&self) {
-        self.receiver.action(&self.info);
+        let entry = self.receiver.action(&self.info);
+        self.entries.borrow_mut().push(entry);
+    }
+}
+
+impl UndoableCommand for ConcreteCommand {
+    fn undo(&self) {
+        if let Some(entry) = self.entries.borrow_mut().pop() {
+            self.receiver.revert(entry);
+        }
     }
 }
 
@@ -43,6 +130,156 @@ impl Invoker {
     pub fn invoke(&self) {
         self.command.execute();
     }
+    pub fn try_invoke(&self) -> Result<(), CommandError> {
+        self.command.try_execute()
+    }
+}
+
+pub struct MacroCommand { commands: Vec<Box<dyn UndoableCommand>> }
+
+impl MacroCommand {
+    pub fn new() -> Self {
+        MacroCommand { commands: Vec::new() }
+    }
+
+    pub fn with(mut self, command: impl UndoableCommand + 'static) -> Self {
+        self.push(command);
+        self
+    }
+
+    pub fn push(&mut self, command: impl UndoableCommand + 'static) {
+        self.commands.push(Box::new(command));
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl Default for MacroCommand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// `execute` still rolls back on failure but has nowhere to report it; callers that need the error
+// (History does) go through `try_execute`.
+impl Command for MacroCommand {
+    fn execute(&self) {
+        let _ = self.try_execute();
+    }
+
+    fn try_execute(&self) -> Result<(), CommandError> {
+        for (index, command) in self.commands.iter().enumerate() {
+            if let Err(cause) = command.try_execute() {
+                for done in self.commands[..index].iter().rev() {
+                    done.undo();
+                }
+                return Err(CommandError::RolledBack { index, cause: Box::new(cause) });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UndoableCommand for MacroCommand {
+    fn undo(&self) {
+        for command in self.commands.iter().rev() {
+            command.undo();
+        }
+    }
+}
+
+pub struct History<C: UndoableCommand = Box<dyn UndoableCommand>> {
+    undo_stack: VecDeque<C>,
+    redo_stack: Vec<C>,
+    max_depth: Option<usize>,
+}
+
+impl<C: UndoableCommand> History<C> {
+    pub fn new() -> Self {
+        History { undo_stack: VecDeque::new(), redo_stack: Vec::new(), max_depth: None }
+    }
+
+    pub fn with_max_depth(max_depth: usize) -> Self {
+        History { max_depth: Some(max_depth), ..Self::new() }
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn execute(&mut self, command: C) -> Result<(), CommandError> {
+        command.try_execute()?;
+        self.redo_stack.clear();
+        self.push_undo(command);
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.undo_stack.pop_back() {
+            Some(command) => {
+                command.undo();
+                self.redo_stack.push(command);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // A command that fails on redo is dropped together with the rest of the redo stack, since
+    // those commands were recorded on top of a state that no longer exists.
+    pub fn redo(&mut self) -> Result<bool, CommandError> {
+        let Some(command) = self.redo_stack.pop() else {
+            return Ok(false);
+        };
+        if let Err(e) = command.try_execute() {
+            self.redo_stack.clear();
+            return Err(e);
+        }
+        self.push_undo(command);
+        Ok(true)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo_stack.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    fn push_undo(&mut self, command: C) {
+        self.undo_stack.push_back(command);
+        if let Some(max) = self.max_depth {
+            while self.undo_stack.len() > max {
+                self.undo_stack.pop_front();
+            }
+        }
+    }
+}
+
+impl<C: UndoableCommand> Default for History<C> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub fn demo() {
@@ -54,6 +291,22 @@ pub fn demo() {
     invoker1.invoke();
     invoker2.invoke();
     println!("[Command demo] history={:?}", receiver.get_log());
+
+    let mut history = History::with_max_depth(10);
+    history.execute(ConcreteCommand::new(receiver.clone(), "Command 3")).unwrap();
+    history.execute(ConcreteCommand::new(receiver.clone(), "Command 4")).unwrap();
+    history.undo();
+    println!("[Command demo] after undo={:?}", receiver.get_log());
+    history.redo().unwrap();
+    println!("[Command demo] after redo={:?}", receiver.get_log());
+
+    let batch = MacroCommand::new()
+        .with(ConcreteCommand::new(receiver.clone(), "Batch 1"))
+        .with(ConcreteCommand::new(receiver.clone(), "Batch 2"));
+    match Invoker::new(Box::new(batch)).try_invoke() {
+        Ok(()) => println!("[Command demo] after batch={:?}", receiver.get_log()),
+        Err(e) => println!("[Command demo] batch failed: {}", e),
+    }
 }
 
 #[cfg(test)]
@@ -68,4 +321,194 @@ mod tests {
         invoker.invoke();
         assert_eq!(receiver.get_log(), vec!["Hello".to_string()]);
     }
+
+    #[test]
+    fn test_undo_redo() {
+        let receiver = Receiver::new();
+        let mut history = History::new();
+        history.execute(ConcreteCommand::new(receiver.clone(), "a")).unwrap();
+        history.execute(ConcreteCommand::new(receiver.clone(), "b")).unwrap();
+        assert!(history.undo());
+        assert_eq!(receiver.get_log(), vec!["a".to_string()]);
+        assert!(history.undo());
+        assert!(receiver.get_log().is_empty());
+        assert!(!history.undo());
+        assert_eq!(history.redo(), Ok(true));
+        assert_eq!(history.redo(), Ok(true));
+        assert_eq!(history.redo(), Ok(false));
+        assert_eq!(receiver.get_log(), vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn test_execute_clears_redo() {
+        let receiver = Receiver::new();
+        let mut history: History = History::new();
+        history.execute(Box::new(ConcreteCommand::new(receiver.clone(), "a"))).unwrap();
+        history.undo();
+        assert!(history.can_redo());
+        history.execute(Box::new(ConcreteCommand::new(receiver.clone(), "b"))).unwrap();
+        assert!(!history.can_redo());
+        assert_eq!(receiver.get_log(), vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn test_max_depth_drops_oldest() {
+        let receiver = Receiver::new();
+        let mut history = History::with_max_depth(2);
+        for info in ["a", "b", "c"] {
+            history.execute(ConcreteCommand::new(receiver.clone(), info)).unwrap();
+        }
+        assert_eq!(history.undo_len(), 2);
+        assert!(history.undo());
+        assert!(history.undo());
+        assert!(!history.undo());
+        assert_eq!(receiver.get_log(), vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_undo_reverts_own_entry() {
+        let receiver = Receiver::new();
+        let first = ConcreteCommand::new(receiver.clone(), "x");
+        let between = ConcreteCommand::new(receiver.clone(), "y");
+        let second = ConcreteCommand::new(receiver.clone(), "x");
+        first.execute();
+        between.execute();
+        second.execute();
+        first.undo();
+        assert_eq!(receiver.get_log(), vec!["y".to_string(), "x".to_string()]);
+        first.undo();
+        assert_eq!(receiver.get_log(), vec!["y".to_string(), "x".to_string()]);
+        second.undo();
+        assert_eq!(receiver.get_log(), vec!["y".to_string()]);
+    }
+
+    struct FailingCommand;
+
+    impl Command for FailingCommand {
+        fn execute(&self) {}
+
+        fn try_execute(&self) -> Result<(), CommandError> {
+            Err(CommandError::Failed("boom".into()))
+        }
+    }
+
+    impl UndoableCommand for FailingCommand {
+        fn undo(&self) {}
+    }
+
+    #[test]
+    fn test_macro_runs_in_order() {
+        let receiver = Receiver::new();
+        let batch = MacroCommand::new()
+            .with(ConcreteCommand::new(receiver.clone(), "a"))
+            .with(ConcreteCommand::new(receiver.clone(), "b"));
+        let invoker = Invoker::new(Box::new(batch));
+        assert_eq!(invoker.try_invoke(), Ok(()));
+        assert_eq!(receiver.get_log(), vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn test_macro_rolls_back_on_failure() {
+        let receiver = Receiver::new();
+        receiver.action("before");
+        let batch = MacroCommand::new()
+            .with(ConcreteCommand::new(receiver.clone(), "a"))
+            .with(ConcreteCommand::new(receiver.clone(), "b"))
+            .with(FailingCommand)
+            .with(ConcreteCommand::new(receiver.clone(), "c"));
+        let err = batch.try_execute().unwrap_err();
+        assert_eq!(
+            err,
+            CommandError::RolledBack {
+                index: 2,
+                cause: Box::new(CommandError::Failed("boom".into())),
+            }
+        );
+        assert_eq!(receiver.get_log(), vec!["before".to_string()]);
+    }
+
+    #[test]
+    fn test_history_skips_failed_macro() {
+        let receiver = Receiver::new();
+        let mut history = History::new();
+        let batch = MacroCommand::new()
+            .with(ConcreteCommand::new(receiver.clone(), "a"))
+            .with(FailingCommand);
+        assert!(history.execute(batch).is_err());
+        assert!(!history.can_undo());
+
+        let batch = MacroCommand::new()
+            .with(ConcreteCommand::new(receiver.clone(), "a"))
+            .with(ConcreteCommand::new(receiver.clone(), "b"));
+        assert!(history.execute(batch).is_ok());
+        assert!(history.undo());
+        assert!(receiver.get_log().is_empty());
+    }
+
+    struct FailsAfter {
+        receiver: Receiver,
+        runs: std::cell::Cell<u32>,
+        limit: u32,
+        entries: RefCell<Vec<Entry>>,
+    }
+
+    impl Command for FailsAfter {
+        fn execute(&self) {
+            let _ = self.try_execute();
+        }
+
+        fn try_execute(&self) -> Result<(), CommandError> {
+            if self.runs.get() == self.limit {
+                return Err(CommandError::Failed("exhausted".into()));
+            }
+            self.runs.set(self.runs.get() + 1);
+            self.entries.borrow_mut().push(self.receiver.action("flaky"));
+            Ok(())
+        }
+    }
+
+    impl UndoableCommand for FailsAfter {
+        fn undo(&self) {
+            if let Some(entry) = self.entries.borrow_mut().pop() {
+                self.receiver.revert(entry);
+            }
+        }
+    }
+
+    #[test]
+    fn test_failed_redo_stays_off_undo_stack() {
+        let receiver = Receiver::new();
+        receiver.action("x");
+        let mut history = History::new();
+        let batch = MacroCommand::new()
+            .with(ConcreteCommand::new(receiver.clone(), "x"))
+            .with(FailsAfter {
+                receiver: receiver.clone(),
+                runs: Default::default(),
+                limit: 1,
+                entries: Default::default(),
+            });
+        history.execute(batch).unwrap();
+        assert!(history.undo());
+        assert_eq!(receiver.get_log(), vec!["x".to_string()]);
+
+        assert!(matches!(history.redo(), Err(CommandError::RolledBack { index: 1, .. })));
+        assert!(!history.can_undo());
+        assert!(!history.can_redo());
+        assert!(!history.undo());
+        assert_eq!(receiver.get_log(), vec!["x".to_string()]);
+    }
+
+    #[test]
+    fn test_macro_execute_rolls_back_on_failure() {
+        let receiver = Receiver::new();
+        let batch = MacroCommand::new()
+            .with(ConcreteCommand::new(receiver.clone(), "a"))
+            .with(FailingCommand);
+        batch.execute();
+        assert!(receiver.get_log().is_empty());
+        let mut history = History::new();
+        assert!(matches!(history.execute(batch), Err(CommandError::RolledBack { index: 1, .. })));
+        assert!(!history.can_undo());
+    }
 }
@@ -0,0 +1,355 @@
+use std::error::Error;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use super::{ConcreteCommand, Entry, Receiver};
+
+pub const HEADER_PREFIX: &str = "oxidized-patterns journal v";
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Do(String),
+    Undo(String),
+}
+
+impl Record {
+    pub fn encode(&self) -> String {
+        match self {
+            Record::Do(info) => format!("do {}", escape(info)),
+            Record::Undo(info) => format!("undo {}", escape(info)),
+        }
+    }
+
+    pub fn decode(line: &str, line_no: usize) -> Result<Self, JournalError> {
+        let (tag, payload) = line.split_once(' ').unwrap_or((line, ""));
+        let record: fn(String) -> Record = match tag {
+            "do" => Record::Do,
+            "undo" => Record::Undo,
+            _ => return Err(JournalError::UnknownRecord { line: line_no, tag: tag.to_string() }),
+        };
+        unescape(payload)
+            .map(record)
+            .map_err(|reason| JournalError::Malformed { line: line_no, reason })
+    }
+}
+
+// Records only carry the text, so an undo is paired with the most recent live entry that has the
+// same text. For undo that follows history order this is exactly the entry the command added.
+pub struct Replay { receiver: Receiver, live: HashMap<String, Vec<Entry>> }
+
+impl Replay {
+    pub fn new(receiver: Receiver) -> Self {
+        Replay { receiver, live: HashMap::new() }
+    }
+
+    pub fn apply(&mut self, record: &Record) {
+        match record {
+            Record::Do(info) => {
+                let entry = self.receiver.action(info);
+                self.live.entry(info.clone()).or_default().push(entry);
+            }
+            Record::Undo(info) => {
+                if let Some(entry) = self.live.get_mut(info).and_then(Vec::pop) {
+                    self.receiver.revert(entry);
+                }
+            }
+        }
+    }
+
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+}
+
+pub trait Journaled {
+    fn record(&self) -> Record;
+}
+
+impl Journaled for ConcreteCommand {
+    fn record(&self) -> Record {
+        Record::Do(self.info.clone())
+    }
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    MissingHeader,
+    UnsupportedVersion { line: usize, found: String },
+    UnknownRecord { line: usize, tag: String },
+    Malformed { line: usize, reason: String },
+    Truncated { line: usize },
+    InvalidUtf8 { line: usize },
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::Io(e) => write!(f, "journal I/O error: {}", e),
+            JournalError::MissingHeader => write!(f, "line 1: missing journal header"),
+            JournalError::UnsupportedVersion { line, found } => {
+                write!(f, "line {}: unsupported journal version '{}'", line, found)
+            }
+            JournalError::UnknownRecord { line, tag } => {
+                write!(f, "line {}: unknown record type '{}'", line, tag)
+            }
+            JournalError::Malformed { line, reason } => write!(f, "line {}: {}", line, reason),
+            JournalError::Truncated { line } => write!(f, "line {}: record is truncated", line),
+            JournalError::InvalidUtf8 { line } => write!(f, "line {}: invalid UTF-8", line),
+        }
+    }
+}
+
+impl Error for JournalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JournalError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for JournalError {
+    fn from(e: io::Error) -> Self {
+        JournalError::Io(e)
+    }
+}
+
+pub struct Journal { file: File }
+
+impl Journal {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}{}", HEADER_PREFIX, VERSION)?;
+        file.flush()?;
+        Ok(Journal { file })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::create(path);
+        }
+        let bytes = fs::read(path)?;
+        // A crash mid-append leaves an incomplete final record. Everything before it is still
+        // valid, so cut the partial line off instead of refusing to reopen the journal.
+        let complete = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let header = format!("{}{}\n", HEADER_PREFIX, VERSION);
+        if complete == 0 && header.as_bytes().starts_with(&bytes) {
+            return Self::create(path);
+        }
+        parse(decode(&bytes[..complete])?)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        if complete < bytes.len() {
+            file.set_len(complete as u64)?;
+        }
+        Ok(Journal { file })
+    }
+
+    pub fn append(&mut self, record: &Record) -> Result<(), JournalError> {
+        writeln!(self.file, "{}", record.encode())?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    pub fn record_execute(&mut self, command: &impl Journaled) -> Result<(), JournalError> {
+        self.append(&command.record())
+    }
+
+    pub fn record_undo(&mut self, command: &impl Journaled) -> Result<(), JournalError> {
+        match command.record() {
+            Record::Do(info) => self.append(&Record::Undo(info)),
+            Record::Undo(info) => self.append(&Record::Do(info)),
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Record>, JournalError> {
+    let mut lines = text.split_inclusive('\n').enumerate().map(|(i, l)| (i + 1, l));
+    let header = match lines.next() {
+        Some((_, line)) if line.ends_with('\n') => line.trim_end_matches(['\r', '\n']),
+        Some((line, _)) => return Err(JournalError::Truncated { line }),
+        None => return Err(JournalError::MissingHeader),
+    };
+    let version = header.strip_prefix(HEADER_PREFIX).ok_or(JournalError::MissingHeader)?;
+    if version.parse::<u32>() != Ok(VERSION) {
+        return Err(JournalError::UnsupportedVersion { line: 1, found: version.to_string() });
+    }
+
+    let mut records = Vec::new();
+    for (line_no, line) in lines {
+        let Some(line) = line.strip_suffix('\n') else {
+            return Err(JournalError::Truncated { line: line_no });
+        };
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        records.push(Record::decode(line, line_no)?);
+    }
+    Ok(records)
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Record>, JournalError> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    parse(decode(&bytes)?)
+}
+
+fn decode(bytes: &[u8]) -> Result<&str, JournalError> {
+    std::str::from_utf8(bytes).map_err(|e| {
+        let line = bytes[..e.valid_up_to()].iter().filter(|&&b| b == b'\n').count() + 1;
+        JournalError::InvalidUtf8 { line }
+    })
+}
+
+pub fn replay(path: impl AsRef<Path>) -> Result<Receiver, JournalError> {
+    let mut replay = Replay::new(Receiver::new());
+    for record in load(path)? {
+        replay.apply(&record);
+    }
+    Ok(replay.receiver)
+}
+
+fn escape(info: &str) -> String {
+    let mut out = String::with_capacity(info.len());
+    for c in info.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(payload: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(payload.len());
+    let mut chars = payload.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => return Err(format!("invalid escape sequence '\\{}'", other)),
+            None => return Err("dangling escape at end of record".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patterns::command::{Command, UndoableCommand};
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("oxidized-journal-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_round_trip_through_disk() {
+        let path = temp_path("round-trip");
+        let receiver = Receiver::new();
+        let first = ConcreteCommand::new(receiver.clone(), "first");
+        let tricky = ConcreteCommand::new(receiver.clone(), "multi\nline \\ text");
+        let mut journal = Journal::create(&path).unwrap();
+        for cmd in [&first, &tricky] {
+            cmd.execute();
+            journal.record_execute(cmd).unwrap();
+        }
+        drop(journal);
+
+        let mut journal = Journal::open(&path).unwrap();
+        first.undo();
+        journal.record_undo(&first).unwrap();
+
+        let restored = replay(&path).unwrap();
+        assert_eq!(restored.get_log(), receiver.get_log());
+        assert_eq!(restored.get_log(), vec!["multi\nline \\ text".to_string()]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_drops_incomplete_final_record() {
+        let path = temp_path("torn-append");
+        let mut journal = Journal::create(&path).unwrap();
+        journal.append(&Record::Do("kept".into())).unwrap();
+        drop(journal);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "do torn \u{e9}").unwrap();
+        drop(file);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.pop();
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(load(&path), Err(JournalError::InvalidUtf8 { line: 3 })));
+
+        let mut journal = Journal::open(&path).unwrap();
+        journal.append(&Record::Do("next".into())).unwrap();
+        assert_eq!(load(&path).unwrap(), [Record::Do("kept".into()), Record::Do("next".into())]);
+
+        fs::write(&path, "oxidized-patt").unwrap();
+        Journal::open(&path).unwrap().append(&Record::Undo("x".into())).unwrap();
+        assert_eq!(load(&path).unwrap(), [Record::Undo("x".into())]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_utf8_reports_line() {
+        let path = temp_path("bad-utf8");
+        let mut journal = Journal::create(&path).unwrap();
+        for info in ["a", "b", "c"] {
+            journal.append(&Record::Do(info.into())).unwrap();
+        }
+        drop(journal);
+        let mut bytes = fs::read(&path).unwrap();
+        let pos = bytes.iter().rposition(|&b| b == b'b').unwrap();
+        bytes[pos] = 0xff;
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(load(&path), Err(JournalError::InvalidUtf8 { line: 3 })));
+        assert!(matches!(Journal::open(&path), Err(JournalError::InvalidUtf8 { line: 3 })));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_header_errors() {
+        assert!(matches!(parse(""), Err(JournalError::MissingHeader)));
+        assert!(matches!(parse("do a\n"), Err(JournalError::MissingHeader)));
+        assert!(matches!(
+            parse("oxidized-patterns journal v9\n"),
+            Err(JournalError::UnsupportedVersion { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_record_reports_line() {
+        let text = "oxidized-patterns journal v1\ndo a\ndo b";
+        assert!(matches!(parse(text), Err(JournalError::Truncated { line: 3 })));
+    }
+
+    #[test]
+    fn test_corrupt_records_report_line() {
+        let text = "oxidized-patterns journal v1\ndo a\nredo b\n";
+        match parse(text) {
+            Err(JournalError::UnknownRecord { line, tag }) => {
+                assert_eq!(line, 3);
+                assert_eq!(tag, "redo");
+            }
+            other => panic!("Expected UnknownRecord, got {:?}", other),
+        }
+        let text = "oxidized-patterns journal v1\ndo bad\\q\n";
+        assert!(matches!(parse(text), Err(JournalError::Malformed { line: 2, .. })));
+        let text = "oxidized-patterns journal v1\nredo bad\\q\n";
+        assert!(matches!(parse(text), Err(JournalError::UnknownRecord { line: 2, .. })));
+    }
+}
@@ -0,0 +1,413 @@
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver as Channel, Sender, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+#[derive(Clone, Default)]
+pub struct SyncReceiver { log: Arc<Mutex<Vec<String>>> }
+
+impl SyncReceiver {
+    pub fn new() -> Self {
+        SyncReceiver { log: Arc::new(Mutex::new(Vec::new())) }
+    }
+    pub fn action(&self, info: &str) {
+        self.log.lock().unwrap().push(info.to_string());
+    }
+    pub fn get_log(&self) -> Vec<String> {
+        self.log.lock().unwrap().clone()
+    }
+}
+
+pub trait SyncCommand: Send + Sync {
+    fn execute(&self);
+}
+
+pub struct SyncConcreteCommand { receiver: SyncReceiver, info: String }
+
+impl SyncConcreteCommand {
+    pub fn new(receiver: SyncReceiver, info: impl Into<String>) -> Self {
+        SyncConcreteCommand { receiver, info: info.into() }
+    }
+}
+
+impl SyncCommand for SyncConcreteCommand {
+    fn execute(&self) {
+        self.receiver.action(&self.info);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueError {
+    ShutDown,
+    Panicked(String),
+    Disconnected,
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::ShutDown => write!(f, "command queue has been shut down"),
+            QueueError::Panicked(message) => write!(f, "command panicked: {}", message),
+            QueueError::Disconnected => write!(f, "command was dropped before it completed"),
+        }
+    }
+}
+
+impl Error for QueueError {}
+
+type Outcome = Result<(), QueueError>;
+
+pub struct CompletionHandle {
+    done: Channel<Outcome>,
+    outcome: OnceCell<Outcome>,
+}
+
+impl CompletionHandle {
+    pub fn wait(self) -> Result<(), QueueError> {
+        match self.outcome.into_inner() {
+            Some(outcome) => outcome,
+            None => self.done.recv().unwrap_or(Err(QueueError::Disconnected)),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        if self.outcome.get().is_some() {
+            return true;
+        }
+        let outcome = match self.done.try_recv() {
+            Ok(outcome) => outcome,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => Err(QueueError::Disconnected),
+        };
+        let _ = self.outcome.set(outcome);
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueOptions {
+    pub workers: usize,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        QueueOptions { workers: 4 }
+    }
+}
+
+struct Job {
+    key: Option<u64>,
+    command: Box<dyn SyncCommand>,
+    done: Sender<Outcome>,
+}
+
+struct State {
+    ready: VecDeque<Job>,
+    pending: HashMap<u64, VecDeque<Job>>,
+    busy_keys: HashSet<u64>,
+    accepting: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+pub struct QueuedInvoker {
+    shared: Arc<Shared>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl QueuedInvoker {
+    pub fn new(options: QueueOptions) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                ready: VecDeque::new(),
+                pending: HashMap::new(),
+                busy_keys: HashSet::new(),
+                accepting: true,
+            }),
+            available: Condvar::new(),
+        });
+        let workers = (0..options.workers.max(1))
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || worker_loop(&shared))
+            })
+            .collect();
+        QueuedInvoker { shared, workers: Mutex::new(workers) }
+    }
+
+    pub fn submit(
+        &self,
+        command: impl SyncCommand + 'static,
+    ) -> Result<CompletionHandle, QueueError> {
+        self.enqueue(None, Box::new(command))
+    }
+
+    pub fn submit_keyed(
+        &self,
+        key: u64,
+        command: impl SyncCommand + 'static,
+    ) -> Result<CompletionHandle, QueueError> {
+        self.enqueue(Some(key), Box::new(command))
+    }
+
+    // A command running on one of our workers may shut the queue down, or drop the last handle
+    // to it. Joining from there would wait on the calling thread itself (or on workers parked
+    // behind its key), so only stop intake and let the workers wind down on their own.
+    pub fn shutdown(&self) {
+        self.shared.state.lock().unwrap().accepting = false;
+        self.shared.available.notify_all();
+        let current = thread::current().id();
+        let mut workers = self.workers.lock().unwrap();
+        if workers.iter().any(|worker| worker.thread().id() == current) {
+            return;
+        }
+        let workers: Vec<_> = workers.drain(..).collect();
+        for worker in workers {
+            let _ = worker.join();
+        }
+    }
+
+    fn enqueue(
+        &self,
+        key: Option<u64>,
+        command: Box<dyn SyncCommand>,
+    ) -> Result<CompletionHandle, QueueError> {
+        let (done, handle) = mpsc::channel();
+        let job = Job { key, command, done };
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.accepting {
+            return Err(QueueError::ShutDown);
+        }
+        match key {
+            Some(k) if state.busy_keys.contains(&k) => {
+                state.pending.entry(k).or_default().push_back(job);
+            }
+            Some(k) => {
+                state.busy_keys.insert(k);
+                state.ready.push_back(job);
+            }
+            None => state.ready.push_back(job),
+        }
+        drop(state);
+        self.shared.available.notify_one();
+        Ok(CompletionHandle { done: handle, outcome: OnceCell::new() })
+    }
+}
+
+impl Drop for QueuedInvoker {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn worker_loop(shared: &Shared) {
+    loop {
+        let job = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if let Some(job) = state.ready.pop_front() {
+                    break job;
+                }
+                let idle = state.busy_keys.is_empty() && state.pending.is_empty();
+                if !state.accepting && idle {
+                    return;
+                }
+                state = shared.available.wait(state).unwrap();
+            }
+        };
+
+        // A panicking command must not take the worker down with it: its key would stay busy
+        // and shutdown would wait forever for the queue to go idle.
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| job.command.execute()))
+            .map_err(|payload| QueueError::Panicked(panic_message(payload.as_ref())));
+
+        if let Some(key) = job.key {
+            let mut state = shared.state.lock().unwrap();
+            let next = state.pending.get_mut(&key).and_then(VecDeque::pop_front);
+            match next {
+                Some(next) => {
+                    if state.pending.get(&key).is_some_and(VecDeque::is_empty) {
+                        state.pending.remove(&key);
+                    }
+                    state.ready.push_back(next);
+                }
+                None => {
+                    state.busy_keys.remove(&key);
+                }
+            }
+        }
+        let _ = job.done.send(outcome);
+        shared.available.notify_all();
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl SyncCommand for Counting {
+        fn execute(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_many_producers() {
+        let invoker = Arc::new(QueuedInvoker::new(QueueOptions { workers: 3 }));
+        let count = Arc::new(AtomicUsize::new(0));
+        let producers: Vec<_> = (0..4)
+            .map(|_| {
+                let invoker = Arc::clone(&invoker);
+                let count = Arc::clone(&count);
+                thread::spawn(move || {
+                    (0..25)
+                        .map(|_| invoker.submit(Counting(Arc::clone(&count))).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for producer in producers {
+            for handle in producer.join().unwrap() {
+                handle.wait().unwrap();
+            }
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 100);
+    }
+
+    struct Slow(SyncReceiver, String);
+
+    impl SyncCommand for Slow {
+        fn execute(&self) {
+            thread::sleep(Duration::from_millis(1));
+            self.0.action(&self.1);
+        }
+    }
+
+    #[test]
+    fn test_per_key_ordering() {
+        let invoker = QueuedInvoker::new(QueueOptions { workers: 4 });
+        let a = SyncReceiver::new();
+        let b = SyncReceiver::new();
+        let mut handles = Vec::new();
+        for i in 0..20 {
+            handles.push(invoker.submit_keyed(1, Slow(a.clone(), i.to_string())).unwrap());
+            handles.push(invoker.submit_keyed(2, Slow(b.clone(), i.to_string())).unwrap());
+        }
+        for handle in handles {
+            handle.wait().unwrap();
+        }
+        let expected: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        assert_eq!(a.get_log(), expected);
+        assert_eq!(b.get_log(), expected);
+    }
+
+    struct Panicking;
+
+    impl SyncCommand for Panicking {
+        fn execute(&self) {
+            panic!("keyed command failed");
+        }
+    }
+
+    #[test]
+    fn test_panicking_command_releases_key() {
+        let invoker = QueuedInvoker::new(QueueOptions { workers: 1 });
+        let receiver = SyncReceiver::new();
+        let failed = invoker.submit_keyed(7, Panicking).unwrap();
+        let next = invoker.submit_keyed(7, SyncConcreteCommand::new(receiver.clone(), "after"));
+        assert_eq!(failed.wait(), Err(QueueError::Panicked("keyed command failed".into())));
+        assert_eq!(next.unwrap().wait(), Ok(()));
+        invoker.shutdown();
+        assert_eq!(receiver.get_log(), vec!["after".to_string()]);
+    }
+
+    #[test]
+    fn test_is_done_does_not_consume_result() {
+        let invoker = QueuedInvoker::new(QueueOptions { workers: 1 });
+        let handle = invoker.submit(SyncConcreteCommand::new(SyncReceiver::new(), "a")).unwrap();
+        while !handle.is_done() {
+            thread::yield_now();
+        }
+        assert!(handle.is_done());
+        assert_eq!(handle.wait(), Ok(()));
+    }
+
+    struct ShutsDown {
+        invoker: Mutex<Option<Arc<QueuedInvoker>>>,
+        go: Mutex<Channel<()>>,
+    }
+
+    impl ShutsDown {
+        fn new(invoker: &Arc<QueuedInvoker>) -> (Self, Sender<()>) {
+            let (go, wait) = mpsc::channel();
+            let invoker = Mutex::new(Some(Arc::clone(invoker)));
+            (ShutsDown { invoker, go: Mutex::new(wait) }, go)
+        }
+    }
+
+    // Takes the invoker out of the command so that, when it is the last handle, Drop runs on the
+    // worker before the command reports completion.
+    impl SyncCommand for ShutsDown {
+        fn execute(&self) {
+            let _ = self.go.lock().unwrap().recv();
+            if let Some(invoker) = self.invoker.lock().unwrap().take() {
+                invoker.shutdown();
+            }
+        }
+    }
+
+    #[test]
+    fn test_shutdown_from_worker_does_not_deadlock() {
+        let invoker = Arc::new(QueuedInvoker::new(QueueOptions { workers: 2 }));
+        let (command, go) = ShutsDown::new(&invoker);
+        let handle = invoker.submit_keyed(3, command).unwrap();
+        go.send(()).unwrap();
+        assert_eq!(handle.wait(), Ok(()));
+        let late = invoker.submit(SyncConcreteCommand::new(SyncReceiver::new(), "late"));
+        assert_eq!(late.err(), Some(QueueError::ShutDown));
+        invoker.shutdown();
+
+        let invoker = Arc::new(QueuedInvoker::new(QueueOptions { workers: 1 }));
+        let (command, go) = ShutsDown::new(&invoker);
+        let handle = invoker.submit(command).unwrap();
+        drop(invoker);
+        go.send(()).unwrap();
+        assert_eq!(handle.wait(), Ok(()));
+    }
+
+    #[test]
+    fn test_shutdown_drains_queue() {
+        let invoker = QueuedInvoker::new(QueueOptions { workers: 1 });
+        let receiver = SyncReceiver::new();
+        for i in 0..10 {
+            invoker.submit(SyncConcreteCommand::new(receiver.clone(), i.to_string())).unwrap();
+        }
+        invoker.shutdown();
+        assert_eq!(receiver.get_log().len(), 10);
+        assert_eq!(
+            invoker.submit(SyncConcreteCommand::new(receiver, "late")).err(),
+            Some(QueueError::ShutDown)
+        );
+    }
+}
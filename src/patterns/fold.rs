@@ -1,21 +1,262 @@
+pub mod arena;
+pub mod check;
+pub mod eval;
+pub mod node_map;
+pub mod parser;
+pub mod passes;
+pub mod printer;
+pub mod rename;
+
+pub use oxidized_patterns_derive::Walk;
+
 pub mod ast {
-    #[derive(Debug)]
-    pub enum Stmt {
+    use super::{walk_block_mut, walk_expr_mut, walk_fn_def_mut, walk_stmt_mut, VisitMut, Walk};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub struct NodeId(u32);
+
+    impl NodeId {
+        // Carried by hand-built nodes until a `NodeIds` pass numbers them.
+        pub const DUMMY: NodeId = NodeId(u32::MAX);
+
+        pub fn as_u32(self) -> u32 {
+            self.0
+        }
+    }
+
+    // Ids are handed out per tree, not from a global counter: every parse numbers its nodes
+    // from 0 in source order, so the same input gets the same ids on every run. A derived
+    // `Clone` copies ids, so a cloned subtree aliases the original's `NodeMap` entries until
+    // it is renumbered by the `NodeIds` that numbered the tree it is grafted into. Separate
+    // parses overlap the same way, so trees meant to share one `NodeMap` need a shared pass.
+    #[derive(Debug, Default)]
+    pub struct NodeIds {
+        next: u32,
+    }
+
+    impl NodeIds {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn next_id(&mut self) -> NodeId {
+            assert!(self.next != NodeId::DUMMY.0, "ran out of node ids for this tree");
+            let id = NodeId(self.next);
+            self.next += 1;
+            id
+        }
+    }
+
+    impl VisitMut for NodeIds {
+        fn visit_name_mut(&mut self, n: &mut Name) {
+            n.id = self.next_id();
+        }
+
+        fn visit_expr_mut(&mut self, e: &mut Expr) {
+            e.id = self.next_id();
+            walk_expr_mut(self, e);
+        }
+
+        fn visit_stmt_mut(&mut self, s: &mut Stmt) {
+            s.id = self.next_id();
+            walk_stmt_mut(self, s);
+        }
+
+        fn visit_block_mut(&mut self, b: &mut Block) {
+            b.id = self.next_id();
+            walk_block_mut(self, b);
+        }
+
+        fn visit_fn_def_mut(&mut self, d: &mut FnDef) {
+            d.id = self.next_id();
+            walk_fn_def_mut(self, d);
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Span {
+        pub start: usize,
+        pub end: usize,
+        pub line: usize,
+        pub column: usize,
+    }
+
+    impl Span {
+        pub fn to(self, other: Span) -> Span {
+            Span {
+                end: other.end,
+                ..self
+            }
+        }
+    }
+
+    // Equality on nodes is structural: ids and spans are bookkeeping and
+    // two trees that print the same compare equal.
+    macro_rules! node {
+        ($ty:ident { $($field:ident),* }) => {
+            impl $ty {
+                pub fn with_span(mut self, span: Span) -> Self {
+                    self.span = Some(span);
+                    self
+                }
+            }
+
+            impl PartialEq for $ty {
+                fn eq(&self, other: &Self) -> bool {
+                    true $(&& self.$field == other.$field)*
+                }
+            }
+
+            impl Eq for $ty {}
+        };
+    }
+
+    #[derive(Debug, Clone, Walk)]
+    pub struct Stmt {
+        pub id: NodeId,
+        pub span: Option<Span>,
+        pub kind: StmtKind,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Walk)]
+    pub enum StmtKind {
         Expr(Box<Expr>),
         Let(Box<Name>, Box<Expr>),
+        Fn(Box<FnDef>),
+    }
+
+    impl Stmt {
+        pub fn new(kind: StmtKind) -> Self {
+            Stmt { id: NodeId::DUMMY, span: None, kind }
+        }
+    }
+
+    impl From<StmtKind> for Stmt {
+        fn from(kind: StmtKind) -> Self {
+            Stmt::new(kind)
+        }
     }
 
-    #[derive(Debug)]
+    node!(Stmt { kind });
+
+    #[derive(Debug, Clone)]
     pub struct Name {
+        pub id: NodeId,
+        pub span: Option<Span>,
         pub value: String,
     }
 
-    #[derive(Debug)]
-    pub enum Expr {
+    impl Name {
+        pub fn new(value: impl Into<String>) -> Self {
+            Name { id: NodeId::DUMMY, span: None, value: value.into() }
+        }
+    }
+
+    node!(Name { value });
+
+    #[derive(Debug, Clone, Walk)]
+    pub struct Block {
+        pub id: NodeId,
+        pub span: Option<Span>,
+        pub stmts: Vec<Box<Stmt>>,
+        pub expr: Option<Box<Expr>>,
+    }
+
+    impl Block {
+        pub fn new(stmts: Vec<Box<Stmt>>, expr: Option<Box<Expr>>) -> Self {
+            Block { id: NodeId::DUMMY, span: None, stmts, expr }
+        }
+    }
+
+    node!(Block { stmts, expr });
+
+    #[derive(Debug, Clone, Walk)]
+    pub struct FnDef {
+        pub id: NodeId,
+        pub span: Option<Span>,
+        pub name: Box<Name>,
+        pub params: Vec<Box<Name>>,
+        pub body: Box<Block>,
+    }
+
+    impl FnDef {
+        pub fn new(name: Box<Name>, params: Vec<Box<Name>>, body: Box<Block>) -> Self {
+            FnDef { id: NodeId::DUMMY, span: None, name, params, body }
+        }
+    }
+
+    node!(FnDef { name, params, body });
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum CmpOp {
+        Eq,
+        Ne,
+        Lt,
+        Le,
+        Gt,
+        Ge,
+    }
+
+    impl CmpOp {
+        pub fn apply(self, left: i64, right: i64) -> bool {
+            match self {
+                CmpOp::Eq => left == right,
+                CmpOp::Ne => left != right,
+                CmpOp::Lt => left < right,
+                CmpOp::Le => left <= right,
+                CmpOp::Gt => left > right,
+                CmpOp::Ge => left >= right,
+            }
+        }
+
+        pub fn symbol(self) -> &'static str {
+            match self {
+                CmpOp::Eq => "==",
+                CmpOp::Ne => "!=",
+                CmpOp::Lt => "<",
+                CmpOp::Le => "<=",
+                CmpOp::Gt => ">",
+                CmpOp::Ge => ">=",
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Walk)]
+    pub struct Expr {
+        pub id: NodeId,
+        pub span: Option<Span>,
+        pub kind: ExprKind,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Walk)]
+    pub enum ExprKind {
         IntLit(i64),
+        BoolLit(bool),
+        Var(Box<Name>),
         Add(Box<Expr>, Box<Expr>),
         Sub(Box<Expr>, Box<Expr>),
+        Cmp(CmpOp, Box<Expr>, Box<Expr>),
+        And(Box<Expr>, Box<Expr>),
+        Or(Box<Expr>, Box<Expr>),
+        Not(Box<Expr>),
+        Block(Box<Block>),
+        If(Box<Expr>, Box<Block>, Option<Box<Block>>),
+        Call(Box<Name>, Vec<Box<Expr>>),
     }
+
+    impl Expr {
+        pub fn new(kind: ExprKind) -> Self {
+            Expr { id: NodeId::DUMMY, span: None, kind }
+        }
+    }
+
+    impl From<ExprKind> for Expr {
+        fn from(kind: ExprKind) -> Self {
+            Expr::new(kind)
+        }
+    }
+
+    node!(Expr { kind });
 }
 
 pub trait Folder {
@@ -24,56 +265,321 @@ pub trait Folder {
     }
 
     fn fold_expr(&mut self, e: Box<ast::Expr>) -> Box<ast::Expr> {
-        use ast::Expr;
-        match *e {
-            Expr::IntLit(_) => e,
-            Expr::Add(left, right) => Box::new(Expr::Add(
-                self.fold_expr(left),
-                self.fold_expr(right),
-            )),
-            Expr::Sub(left, right) => Box::new(Expr::Sub(
-                self.fold_expr(left),
-                self.fold_expr(right),
-            )),
-        }
+        super_fold_expr(self, e)
     }
 
     fn fold_stmt(&mut self, s: Box<ast::Stmt>) -> Box<ast::Stmt> {
-        use ast::Stmt;
-        match *s {
-            Stmt::Expr(e) => Box::new(Stmt::Expr(self.fold_expr(e))),
-            Stmt::Let(n, e) => Box::new(Stmt::Let(
-                self.fold_name(n),
-                self.fold_expr(e),
-            )),
+        super_fold_stmt(self, s)
+    }
+
+    fn fold_block(&mut self, b: Box<ast::Block>) -> Box<ast::Block> {
+        super_fold_block(self, b)
+    }
+
+    fn fold_fn_def(&mut self, d: Box<ast::FnDef>) -> Box<ast::FnDef> {
+        super_fold_fn_def(self, d)
+    }
+}
+
+pub trait Walk<F: ?Sized>: Sized {
+    fn walk(self, folder: &mut F) -> Self;
+}
+
+macro_rules! walk_leaf {
+    ($($ty:ty),*) => {
+        $(impl<F: ?Sized> Walk<F> for $ty {
+            fn walk(self, _folder: &mut F) -> Self {
+                self
+            }
+        })*
+    };
+}
+
+walk_leaf!(bool, i32, i64, u32, u64, usize, String, ast::CmpOp, ast::NodeId, ast::Span);
+
+impl<F: ?Sized, T: Walk<F>> Walk<F> for Vec<T> {
+    fn walk(self, folder: &mut F) -> Self {
+        self.into_iter().map(|item| item.walk(folder)).collect()
+    }
+}
+
+impl<F: ?Sized, T: Walk<F>> Walk<F> for Option<T> {
+    fn walk(self, folder: &mut F) -> Self {
+        self.map(|item| item.walk(folder))
+    }
+}
+
+macro_rules! walk_via_folder {
+    ($($ty:ident => $method:ident),*) => {
+        $(impl<F: Folder + ?Sized> Walk<F> for Box<ast::$ty> {
+            fn walk(self, folder: &mut F) -> Self {
+                folder.$method(self)
+            }
+        })*
+    };
+}
+
+walk_via_folder!(
+    Name => fold_name,
+    Expr => fold_expr,
+    Stmt => fold_stmt,
+    Block => fold_block,
+    FnDef => fold_fn_def
+);
+
+#[allow(clippy::boxed_local)]
+pub fn super_fold_expr<F: Folder + ?Sized>(f: &mut F, e: Box<ast::Expr>) -> Box<ast::Expr> {
+    Box::new((*e).walk(f))
+}
+
+#[allow(clippy::boxed_local)]
+pub fn super_fold_stmt<F: Folder + ?Sized>(f: &mut F, s: Box<ast::Stmt>) -> Box<ast::Stmt> {
+    Box::new((*s).walk(f))
+}
+
+#[allow(clippy::boxed_local)]
+pub fn super_fold_block<F: Folder + ?Sized>(f: &mut F, b: Box<ast::Block>) -> Box<ast::Block> {
+    Box::new((*b).walk(f))
+}
+
+#[allow(clippy::boxed_local)]
+pub fn super_fold_fn_def<F: Folder + ?Sized>(f: &mut F, d: Box<ast::FnDef>) -> Box<ast::FnDef> {
+    Box::new((*d).walk(f))
+}
+
+pub trait Visit {
+    fn visit_name(&mut self, _n: &ast::Name) {}
+
+    fn visit_expr(&mut self, e: &ast::Expr) {
+        walk_expr(self, e);
+    }
+
+    fn visit_stmt(&mut self, s: &ast::Stmt) {
+        walk_stmt(self, s);
+    }
+
+    fn visit_block(&mut self, b: &ast::Block) {
+        walk_block(self, b);
+    }
+
+    fn visit_fn_def(&mut self, d: &ast::FnDef) {
+        walk_fn_def(self, d);
+    }
+}
+
+pub fn walk_expr<V: Visit + ?Sized>(v: &mut V, e: &ast::Expr) {
+    use ast::ExprKind as Expr;
+    match &e.kind {
+        Expr::IntLit(_) | Expr::BoolLit(_) => {}
+        Expr::Var(n) => v.visit_name(n),
+        Expr::Add(left, right)
+        | Expr::Sub(left, right)
+        | Expr::Cmp(_, left, right)
+        | Expr::And(left, right)
+        | Expr::Or(left, right) => {
+            v.visit_expr(left);
+            v.visit_expr(right);
+        }
+        Expr::Not(inner) => v.visit_expr(inner),
+        Expr::Block(b) => v.visit_block(b),
+        Expr::If(cond, then, els) => {
+            v.visit_expr(cond);
+            v.visit_block(then);
+            if let Some(els) = els {
+                v.visit_block(els);
+            }
+        }
+        Expr::Call(n, args) => {
+            v.visit_name(n);
+            for arg in args {
+                v.visit_expr(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_stmt<V: Visit + ?Sized>(v: &mut V, s: &ast::Stmt) {
+    use ast::StmtKind as Stmt;
+    match &s.kind {
+        Stmt::Expr(e) => v.visit_expr(e),
+        Stmt::Let(n, e) => {
+            v.visit_name(n);
+            v.visit_expr(e);
         }
+        Stmt::Fn(d) => v.visit_fn_def(d),
     }
 }
 
+pub fn walk_block<V: Visit + ?Sized>(v: &mut V, b: &ast::Block) {
+    for stmt in &b.stmts {
+        v.visit_stmt(stmt);
+    }
+    if let Some(e) = &b.expr {
+        v.visit_expr(e);
+    }
+}
+
+pub fn walk_fn_def<V: Visit + ?Sized>(v: &mut V, d: &ast::FnDef) {
+    v.visit_name(&d.name);
+    for param in &d.params {
+        v.visit_name(param);
+    }
+    v.visit_block(&d.body);
+}
+
+pub trait VisitMut {
+    fn visit_name_mut(&mut self, _n: &mut ast::Name) {}
+
+    fn visit_expr_mut(&mut self, e: &mut ast::Expr) {
+        walk_expr_mut(self, e);
+    }
+
+    fn visit_stmt_mut(&mut self, s: &mut ast::Stmt) {
+        walk_stmt_mut(self, s);
+    }
+
+    fn visit_block_mut(&mut self, b: &mut ast::Block) {
+        walk_block_mut(self, b);
+    }
+
+    fn visit_fn_def_mut(&mut self, d: &mut ast::FnDef) {
+        walk_fn_def_mut(self, d);
+    }
+}
+
+pub fn walk_expr_mut<V: VisitMut + ?Sized>(v: &mut V, e: &mut ast::Expr) {
+    use ast::ExprKind as Expr;
+    match &mut e.kind {
+        Expr::IntLit(_) | Expr::BoolLit(_) => {}
+        Expr::Var(n) => v.visit_name_mut(n),
+        Expr::Add(left, right)
+        | Expr::Sub(left, right)
+        | Expr::Cmp(_, left, right)
+        | Expr::And(left, right)
+        | Expr::Or(left, right) => {
+            v.visit_expr_mut(left);
+            v.visit_expr_mut(right);
+        }
+        Expr::Not(inner) => v.visit_expr_mut(inner),
+        Expr::Block(b) => v.visit_block_mut(b),
+        Expr::If(cond, then, els) => {
+            v.visit_expr_mut(cond);
+            v.visit_block_mut(then);
+            if let Some(els) = els {
+                v.visit_block_mut(els);
+            }
+        }
+        Expr::Call(n, args) => {
+            v.visit_name_mut(n);
+            for arg in args {
+                v.visit_expr_mut(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitMut + ?Sized>(v: &mut V, s: &mut ast::Stmt) {
+    use ast::StmtKind as Stmt;
+    match &mut s.kind {
+        Stmt::Expr(e) => v.visit_expr_mut(e),
+        Stmt::Let(n, e) => {
+            v.visit_name_mut(n);
+            v.visit_expr_mut(e);
+        }
+        Stmt::Fn(d) => v.visit_fn_def_mut(d),
+    }
+}
+
+pub fn walk_block_mut<V: VisitMut + ?Sized>(v: &mut V, b: &mut ast::Block) {
+    for stmt in &mut b.stmts {
+        v.visit_stmt_mut(stmt);
+    }
+    if let Some(e) = &mut b.expr {
+        v.visit_expr_mut(e);
+    }
+}
+
+pub fn walk_fn_def_mut<V: VisitMut + ?Sized>(v: &mut V, d: &mut ast::FnDef) {
+    v.visit_name_mut(&mut d.name);
+    for param in &mut d.params {
+        v.visit_name_mut(param);
+    }
+    v.visit_block_mut(&mut d.body);
+}
+
 pub struct Renamer;
 
 impl Folder for Renamer {
-    fn fold_name(&mut self, _n: Box<ast::Name>) -> Box<ast::Name> {
-        Box::new(ast::Name { value: "foo".to_string() })
+    fn fold_name(&mut self, n: Box<ast::Name>) -> Box<ast::Name> {
+        Box::new(ast::Name {
+            value: "foo".to_string(),
+            ..*n
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct LiteralCounter {
+    pub count: usize,
+}
+
+impl Visit for LiteralCounter {
+    fn visit_expr(&mut self, e: &ast::Expr) {
+        if let ast::ExprKind::IntLit(_) = e.kind {
+            self.count += 1;
+        }
+        walk_expr(self, e);
     }
 }
 
 pub fn demo() {
     use ast::*;
-    let stmt = Stmt::Let(
-        Box::new(Name { value: "x".into() }),
-        Box::new(Expr::Add(
-            Box::new(Expr::IntLit(1)),
-            Box::new(Expr::Sub(
-                Box::new(Expr::IntLit(5)),
-                Box::new(Expr::IntLit(2)),
-            )),
-        )),
-    );
-    println!("[Fold AST demo] Original: {:?}", stmt);
+    let stmt = Stmt::new(StmtKind::Let(
+        Box::new(Name::new("x")),
+        Box::new(
+            ExprKind::Add(
+                Box::new(ExprKind::IntLit(1).into()),
+                Box::new(
+                    ExprKind::Sub(
+                        Box::new(ExprKind::IntLit(5).into()),
+                        Box::new(ExprKind::IntLit(2).into()),
+                    )
+                    .into(),
+                ),
+            )
+            .into(),
+        ),
+    ));
+    println!("[Fold AST demo] Original: {}", stmt);
+    let mut counter = LiteralCounter::default();
+    counter.visit_stmt(&stmt);
+    println!("[Fold AST demo] Literals: {}", counter.count);
     let mut renamer = Renamer;
     let new_stmt = renamer.fold_stmt(Box::new(stmt));
-    println!("[Fold AST demo] Renamed: {:?}", new_stmt);
+    println!("[Fold AST demo] Renamed: {}", new_stmt);
+    let mut manager = passes::PassManager::default();
+    let optimised = manager.run_stmt(new_stmt);
+    println!("[Fold AST demo] Optimised: {} ({} iterations)", optimised, manager.iterations());
+
+    let source = "fn double(n) { n + n }\nlet y = double(x) - 1;\nlet y = z;";
+    let mut checker = check::Checker::new().with_global("x", check::Ty::Int);
+    checker.check(parser::parse_stmts(source).expect("demo program should parse"));
+    for diagnostic in &checker.diagnostics {
+        println!("[Fold AST demo] Check: {}", diagnostic);
+    }
+
+    let source = "fn double(n) { n + n }\nlet y = double(x) - 1;\ny < 10;";
+    let program = parser::parse_stmts(source).expect("demo program should parse");
+    let env = eval::Environment::new().with("x", eval::Value::Int(4));
+    let mut evaluator = eval::Evaluator::new(env);
+    match evaluator.run(program.iter().map(|s| &**s)) {
+        Ok(values) => println!(
+            "[Fold AST demo] Eval: {:?}, bindings {:?}",
+            values,
+            evaluator.environment().values()
+        ),
+        Err(e) => println!("[Fold AST demo] Eval error: {}", e),
+    }
 }
 
 #[cfg(test)]
@@ -81,16 +587,17 @@ mod tests {
     use super::*;
     use ast::*;
 
+    fn int(v: i64) -> Box<Expr> {
+        Box::new(ExprKind::IntLit(v).into())
+    }
+
     #[test]
     fn test_renamer_on_let() {
-        let stmt = Stmt::Let(
-            Box::new(Name { value: "x".into() }),
-            Box::new(Expr::IntLit(3)),
-        );
+        let stmt = Stmt::new(StmtKind::Let(Box::new(Name::new("x")), int(3)));
         let mut renamer = Renamer;
-        if let Stmt::Let(n, e) = *renamer.fold_stmt(Box::new(stmt)) {
+        if let StmtKind::Let(n, e) = renamer.fold_stmt(Box::new(stmt)).kind {
             assert_eq!(n.value, "foo");
-            if let Expr::IntLit(v) = *e {
+            if let ExprKind::IntLit(v) = e.kind {
                 assert_eq!(v, 3);
             } else {
                 panic!("Expected IntLit");
@@ -102,21 +609,73 @@ mod tests {
 
     #[test]
     fn test_renamer_on_nested_expr() {
-        let stmt = Stmt::Expr(Box::new(Expr::Add(
-            Box::new(Expr::IntLit(2)),
-            Box::new(Expr::Sub(
-                Box::new(Expr::IntLit(4)),
-                Box::new(Expr::IntLit(1)),
-            )),
+        let stmt = Stmt::new(StmtKind::Expr(Box::new(
+            ExprKind::Add(int(2), Box::new(ExprKind::Sub(int(4), int(1)).into())).into(),
         )));
         let mut renamer = Renamer;
         let res = renamer.fold_stmt(Box::new(stmt));
         // Only names are renamed, expressions unchanged
-        if let ast::Stmt::Expr(e) = *res {
+        if let StmtKind::Expr(e) = res.kind {
             // Should still be the same structure
-            assert!(matches!(*e, Expr::Add(_, _)));
+            assert!(matches!(e.kind, ExprKind::Add(_, _)));
         } else {
             panic!("Expected Expr statement");
         }
     }
+
+    #[test]
+    fn test_renamer_reaches_every_new_node() {
+        let source = "fn f(a) { let b = if a < 1 { g(a) } else { !c }; b }";
+        let stmt = parser::parse_stmts(source).unwrap().remove(0);
+        let renamed = Renamer.fold_stmt(stmt);
+        assert_eq!(
+            renamed.to_string(),
+            "fn foo(foo) {\n    let foo = if foo < 1 { foo(foo) } else { !foo };\n    foo\n}"
+        );
+    }
+
+    #[test]
+    fn test_visit_counts_without_consuming() {
+        let stmt = Stmt::new(StmtKind::Let(
+            Box::new(Name::new("x")),
+            Box::new(ExprKind::Sub(int(5), Box::new(ExprKind::Add(int(1), int(2)).into())).into()),
+        ));
+        let mut counter = LiteralCounter::default();
+        counter.visit_stmt(&stmt);
+        counter.visit_stmt(&stmt);
+        assert_eq!(counter.count, 6);
+
+        struct Names(Vec<String>);
+        impl Visit for Names {
+            fn visit_name(&mut self, n: &Name) {
+                self.0.push(n.value.clone());
+            }
+        }
+        let mut names = Names(Vec::new());
+        names.visit_stmt(&stmt);
+        assert_eq!(names.0, vec!["x".to_string()]);
+    }
+
+    #[test]
+    fn test_visit_mut_in_place() {
+        struct Doubler;
+        impl VisitMut for Doubler {
+            fn visit_name_mut(&mut self, n: &mut Name) {
+                n.value.push('2');
+            }
+
+            fn visit_expr_mut(&mut self, e: &mut Expr) {
+                if let ExprKind::IntLit(v) = &mut e.kind {
+                    *v *= 2;
+                }
+                walk_expr_mut(self, e);
+            }
+        }
+        let mut stmt = Stmt::new(StmtKind::Let(
+            Box::new(Name::new("x")),
+            Box::new(ExprKind::Add(int(1), int(2)).into()),
+        ));
+        Doubler.visit_stmt_mut(&mut stmt);
+        assert_eq!(stmt.to_string(), "let x2 = 2 + 4;");
+    }
 }
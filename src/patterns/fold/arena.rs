@@ -0,0 +1,465 @@
+use super::ast::{self, CmpOp, NodeId, Span};
+
+macro_rules! node_ref {
+    ($name:ident) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct $name(u32);
+
+        impl $name {
+            fn index(self) -> usize {
+                self.0 as usize
+            }
+        }
+    };
+}
+
+node_ref!(ExprRef);
+node_ref!(StmtRef);
+node_ref!(BlockRef);
+node_ref!(FnRef);
+node_ref!(NameRef);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    IntLit(i64),
+    BoolLit(bool),
+    Var(NameRef),
+    Add(ExprRef, ExprRef),
+    Sub(ExprRef, ExprRef),
+    Cmp(CmpOp, ExprRef, ExprRef),
+    And(ExprRef, ExprRef),
+    Or(ExprRef, ExprRef),
+    Not(ExprRef),
+    Block(BlockRef),
+    If(ExprRef, BlockRef, Option<BlockRef>),
+    Call(NameRef, Vec<ExprRef>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StmtKind {
+    Expr(ExprRef),
+    Let(NameRef, ExprRef),
+    Fn(FnRef),
+}
+
+#[derive(Debug, Clone)]
+pub struct Node<K> {
+    pub id: NodeId,
+    pub span: Option<Span>,
+    pub kind: K,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockKind {
+    pub stmts: Vec<StmtRef>,
+    pub expr: Option<ExprRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FnKind {
+    pub name: NameRef,
+    pub params: Vec<NameRef>,
+    pub body: BlockRef,
+}
+
+pub type Expr = Node<ExprKind>;
+pub type Stmt = Node<StmtKind>;
+pub type Block = Node<BlockKind>;
+pub type FnDef = Node<FnKind>;
+pub type Name = Node<String>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Arena {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+    blocks: Vec<Block>,
+    fns: Vec<FnDef>,
+    names: Vec<Name>,
+}
+
+macro_rules! table {
+    ($node:ident, $alloc:ident, $rebuild:ident, $field:ident, $ref:ident, $kind:ty) => {
+        pub fn $node(&self, r: $ref) -> &Node<$kind> {
+            &self.$field[r.index()]
+        }
+
+        pub fn $alloc(&mut self, node: Node<$kind>) -> $ref {
+            let index = u32::try_from(self.$field.len()).expect("arena table is full");
+            self.$field.push(node);
+            $ref(index)
+        }
+
+        pub fn $rebuild(&mut self, r: $ref, kind: $kind) -> $ref {
+            let Node { id, span, .. } = self.$field[r.index()];
+            self.$alloc(Node { id, span, kind })
+        }
+    };
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Arena::default()
+    }
+
+    table!(expr, alloc_expr, rebuild_expr, exprs, ExprRef, ExprKind);
+    table!(stmt, alloc_stmt, rebuild_stmt, stmts, StmtRef, StmtKind);
+    table!(block, alloc_block, rebuild_block, blocks, BlockRef, BlockKind);
+    table!(fn_def, alloc_fn_def, rebuild_fn_def, fns, FnRef, FnKind);
+    table!(name, alloc_name, rebuild_name, names, NameRef, String);
+
+    pub fn len(&self) -> usize {
+        self.exprs.len() + self.stmts.len() + self.blocks.len() + self.fns.len() + self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn import_name(&mut self, n: &ast::Name) -> NameRef {
+        self.alloc_name(Node { id: n.id, span: n.span, kind: n.value.clone() })
+    }
+
+    pub fn import_expr(&mut self, e: &ast::Expr) -> ExprRef {
+        use ast::ExprKind as E;
+        let kind = match &e.kind {
+            E::IntLit(v) => ExprKind::IntLit(*v),
+            E::BoolLit(b) => ExprKind::BoolLit(*b),
+            E::Var(n) => ExprKind::Var(self.import_name(n)),
+            E::Add(l, r) => ExprKind::Add(self.import_expr(l), self.import_expr(r)),
+            E::Sub(l, r) => ExprKind::Sub(self.import_expr(l), self.import_expr(r)),
+            E::Cmp(op, l, r) => ExprKind::Cmp(*op, self.import_expr(l), self.import_expr(r)),
+            E::And(l, r) => ExprKind::And(self.import_expr(l), self.import_expr(r)),
+            E::Or(l, r) => ExprKind::Or(self.import_expr(l), self.import_expr(r)),
+            E::Not(inner) => ExprKind::Not(self.import_expr(inner)),
+            E::Block(b) => ExprKind::Block(self.import_block(b)),
+            E::If(cond, then, els) => ExprKind::If(
+                self.import_expr(cond),
+                self.import_block(then),
+                els.as_ref().map(|b| self.import_block(b)),
+            ),
+            E::Call(n, args) => {
+                let name = self.import_name(n);
+                ExprKind::Call(name, args.iter().map(|a| self.import_expr(a)).collect())
+            }
+        };
+        self.alloc_expr(Node { id: e.id, span: e.span, kind })
+    }
+
+    pub fn import_stmt(&mut self, s: &ast::Stmt) -> StmtRef {
+        let kind = match &s.kind {
+            ast::StmtKind::Expr(e) => StmtKind::Expr(self.import_expr(e)),
+            ast::StmtKind::Let(n, e) => StmtKind::Let(self.import_name(n), self.import_expr(e)),
+            ast::StmtKind::Fn(d) => StmtKind::Fn(self.import_fn_def(d)),
+        };
+        self.alloc_stmt(Node { id: s.id, span: s.span, kind })
+    }
+
+    pub fn import_block(&mut self, b: &ast::Block) -> BlockRef {
+        let kind = BlockKind {
+            stmts: b.stmts.iter().map(|s| self.import_stmt(s)).collect(),
+            expr: b.expr.as_ref().map(|e| self.import_expr(e)),
+        };
+        self.alloc_block(Node { id: b.id, span: b.span, kind })
+    }
+
+    pub fn import_fn_def(&mut self, d: &ast::FnDef) -> FnRef {
+        let kind = FnKind {
+            name: self.import_name(&d.name),
+            params: d.params.iter().map(|p| self.import_name(p)).collect(),
+            body: self.import_block(&d.body),
+        };
+        self.alloc_fn_def(Node { id: d.id, span: d.span, kind })
+    }
+
+    pub fn export_name(&self, r: NameRef) -> Box<ast::Name> {
+        let Node { id, span, kind } = self.name(r);
+        Box::new(ast::Name { id: *id, span: *span, value: kind.clone() })
+    }
+
+    pub fn export_expr(&self, r: ExprRef) -> Box<ast::Expr> {
+        use ast::ExprKind as E;
+        let node = self.expr(r);
+        let kind = match &node.kind {
+            ExprKind::IntLit(v) => E::IntLit(*v),
+            ExprKind::BoolLit(b) => E::BoolLit(*b),
+            ExprKind::Var(n) => E::Var(self.export_name(*n)),
+            ExprKind::Add(l, r) => E::Add(self.export_expr(*l), self.export_expr(*r)),
+            ExprKind::Sub(l, r) => E::Sub(self.export_expr(*l), self.export_expr(*r)),
+            ExprKind::Cmp(op, l, r) => E::Cmp(*op, self.export_expr(*l), self.export_expr(*r)),
+            ExprKind::And(l, r) => E::And(self.export_expr(*l), self.export_expr(*r)),
+            ExprKind::Or(l, r) => E::Or(self.export_expr(*l), self.export_expr(*r)),
+            ExprKind::Not(inner) => E::Not(self.export_expr(*inner)),
+            ExprKind::Block(b) => E::Block(self.export_block(*b)),
+            ExprKind::If(cond, then, els) => E::If(
+                self.export_expr(*cond),
+                self.export_block(*then),
+                els.map(|b| self.export_block(b)),
+            ),
+            ExprKind::Call(n, args) => {
+                E::Call(self.export_name(*n), args.iter().map(|a| self.export_expr(*a)).collect())
+            }
+        };
+        Box::new(ast::Expr { id: node.id, span: node.span, kind })
+    }
+
+    pub fn export_stmt(&self, r: StmtRef) -> Box<ast::Stmt> {
+        let node = self.stmt(r);
+        let kind = match &node.kind {
+            StmtKind::Expr(e) => ast::StmtKind::Expr(self.export_expr(*e)),
+            StmtKind::Let(n, e) => ast::StmtKind::Let(self.export_name(*n), self.export_expr(*e)),
+            StmtKind::Fn(d) => ast::StmtKind::Fn(self.export_fn_def(*d)),
+        };
+        Box::new(ast::Stmt { id: node.id, span: node.span, kind })
+    }
+
+    pub fn export_block(&self, r: BlockRef) -> Box<ast::Block> {
+        let node = self.block(r);
+        Box::new(ast::Block {
+            id: node.id,
+            span: node.span,
+            stmts: node.kind.stmts.iter().map(|s| self.export_stmt(*s)).collect(),
+            expr: node.kind.expr.map(|e| self.export_expr(e)),
+        })
+    }
+
+    pub fn export_fn_def(&self, r: FnRef) -> Box<ast::FnDef> {
+        let node = self.fn_def(r);
+        Box::new(ast::FnDef {
+            id: node.id,
+            span: node.span,
+            name: self.export_name(node.kind.name),
+            params: node.kind.params.iter().map(|p| self.export_name(*p)).collect(),
+            body: self.export_block(node.kind.body),
+        })
+    }
+}
+
+pub trait ArenaFolder {
+    fn fold_name(&mut self, _arena: &mut Arena, n: NameRef) -> NameRef {
+        n
+    }
+
+    fn fold_expr(&mut self, arena: &mut Arena, e: ExprRef) -> ExprRef {
+        super_fold_expr(self, arena, e)
+    }
+
+    fn fold_stmt(&mut self, arena: &mut Arena, s: StmtRef) -> StmtRef {
+        super_fold_stmt(self, arena, s)
+    }
+
+    fn fold_block(&mut self, arena: &mut Arena, b: BlockRef) -> BlockRef {
+        super_fold_block(self, arena, b)
+    }
+
+    fn fold_fn_def(&mut self, arena: &mut Arena, d: FnRef) -> FnRef {
+        super_fold_fn_def(self, arena, d)
+    }
+}
+
+// Each super_fold_* only allocates when a child came back as a different
+// ref; an unchanged subtree is returned as-is and shared with the input.
+// Lists of children are read back out of the arena one ref at a time, so
+// nothing is copied for a node unless one of its children changed.
+fn fold_refs<R: Copy + PartialEq>(
+    arena: &mut Arena,
+    len: usize,
+    get: impl Fn(&Arena, usize) -> R,
+    mut fold: impl FnMut(&mut Arena, R) -> R,
+) -> Option<Vec<R>> {
+    let mut changed: Option<Vec<R>> = None;
+    for i in 0..len {
+        let old = get(arena, i);
+        let new = fold(arena, old);
+        if let Some(refs) = &mut changed {
+            refs.push(new);
+        } else if new != old {
+            let mut refs = Vec::with_capacity(len);
+            refs.extend((0..i).map(|j| get(arena, j)));
+            refs.push(new);
+            changed = Some(refs);
+        }
+    }
+    changed
+}
+
+fn call_arg(arena: &Arena, e: ExprRef, i: usize) -> ExprRef {
+    match &arena.expr(e).kind {
+        ExprKind::Call(_, args) => args[i],
+        _ => unreachable!("call_arg on a non-call expression"),
+    }
+}
+
+pub fn super_fold_expr<F>(f: &mut F, arena: &mut Arena, e: ExprRef) -> ExprRef
+where
+    F: ArenaFolder + ?Sized,
+{
+    let folded = match arena.expr(e).kind {
+        ExprKind::IntLit(_) | ExprKind::BoolLit(_) => return e,
+        ExprKind::Var(n) => ExprKind::Var(f.fold_name(arena, n)),
+        ExprKind::Add(l, r) => ExprKind::Add(f.fold_expr(arena, l), f.fold_expr(arena, r)),
+        ExprKind::Sub(l, r) => ExprKind::Sub(f.fold_expr(arena, l), f.fold_expr(arena, r)),
+        ExprKind::Cmp(op, l, r) => ExprKind::Cmp(op, f.fold_expr(arena, l), f.fold_expr(arena, r)),
+        ExprKind::And(l, r) => ExprKind::And(f.fold_expr(arena, l), f.fold_expr(arena, r)),
+        ExprKind::Or(l, r) => ExprKind::Or(f.fold_expr(arena, l), f.fold_expr(arena, r)),
+        ExprKind::Not(inner) => ExprKind::Not(f.fold_expr(arena, inner)),
+        ExprKind::Block(b) => ExprKind::Block(f.fold_block(arena, b)),
+        ExprKind::If(cond, then, els) => ExprKind::If(
+            f.fold_expr(arena, cond),
+            f.fold_block(arena, then),
+            els.map(|b| f.fold_block(arena, b)),
+        ),
+        ExprKind::Call(n, ref args) => {
+            let len = args.len();
+            let name = f.fold_name(arena, n);
+            let arg = |arena: &Arena, i| call_arg(arena, e, i);
+            let args = fold_refs(arena, len, arg, |arena, a| f.fold_expr(arena, a));
+            let args = match args {
+                Some(args) => args,
+                None if name == n => return e,
+                None => (0..len).map(|i| call_arg(arena, e, i)).collect(),
+            };
+            return arena.rebuild_expr(e, ExprKind::Call(name, args));
+        }
+    };
+    if folded == arena.expr(e).kind { e } else { arena.rebuild_expr(e, folded) }
+}
+
+pub fn super_fold_stmt<F>(f: &mut F, arena: &mut Arena, s: StmtRef) -> StmtRef
+where
+    F: ArenaFolder + ?Sized,
+{
+    let folded = match arena.stmt(s).kind {
+        StmtKind::Expr(e) => StmtKind::Expr(f.fold_expr(arena, e)),
+        StmtKind::Let(n, e) => StmtKind::Let(f.fold_name(arena, n), f.fold_expr(arena, e)),
+        StmtKind::Fn(d) => StmtKind::Fn(f.fold_fn_def(arena, d)),
+    };
+    if folded == arena.stmt(s).kind { s } else { arena.rebuild_stmt(s, folded) }
+}
+
+pub fn super_fold_block<F>(f: &mut F, arena: &mut Arena, b: BlockRef) -> BlockRef
+where
+    F: ArenaFolder + ?Sized,
+{
+    let BlockKind { ref stmts, expr } = arena.block(b).kind;
+    let len = stmts.len();
+    let stmt = |arena: &Arena, i| arena.block(b).kind.stmts[i];
+    let folded_stmts = fold_refs(arena, len, stmt, |arena, s| f.fold_stmt(arena, s));
+    let folded_expr = expr.map(|e| f.fold_expr(arena, e));
+    if folded_stmts.is_none() && folded_expr == expr {
+        return b;
+    }
+    let stmts = folded_stmts.unwrap_or_else(|| arena.block(b).kind.stmts.clone());
+    arena.rebuild_block(b, BlockKind { stmts, expr: folded_expr })
+}
+
+pub fn super_fold_fn_def<F>(f: &mut F, arena: &mut Arena, d: FnRef) -> FnRef
+where
+    F: ArenaFolder + ?Sized,
+{
+    let FnKind { name, ref params, body } = arena.fn_def(d).kind;
+    let len = params.len();
+    let folded_name = f.fold_name(arena, name);
+    let param = |arena: &Arena, i| arena.fn_def(d).kind.params[i];
+    let folded_params = fold_refs(arena, len, param, |arena, p| f.fold_name(arena, p));
+    let folded_body = f.fold_block(arena, body);
+    if folded_name == name && folded_params.is_none() && folded_body == body {
+        return d;
+    }
+    let params = folded_params.unwrap_or_else(|| arena.fn_def(d).kind.params.clone());
+    arena.rebuild_fn_def(d, FnKind { name: folded_name, params, body: folded_body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::parse_stmts;
+    use super::*;
+
+    struct Identity;
+
+    impl ArenaFolder for Identity {}
+
+    struct AddFolder;
+
+    impl ArenaFolder for AddFolder {
+        fn fold_expr(&mut self, arena: &mut Arena, e: ExprRef) -> ExprRef {
+            let e = super_fold_expr(self, arena, e);
+            let ExprKind::Add(l, r) = arena.expr(e).kind else { return e };
+            match (&arena.expr(l).kind, &arena.expr(r).kind) {
+                (ExprKind::IntLit(a), ExprKind::IntLit(b)) => {
+                    let sum = ExprKind::IntLit(a + b);
+                    arena.rebuild_expr(e, sum)
+                }
+                _ => e,
+            }
+        }
+    }
+
+    fn import(source: &str) -> (Arena, Vec<StmtRef>) {
+        let mut arena = Arena::new();
+        let roots = parse_stmts(source).unwrap().iter().map(|s| arena.import_stmt(s)).collect();
+        (arena, roots)
+    }
+
+    #[test]
+    fn test_round_trip_through_arena() {
+        let source = "fn f(a, b) { if a < b { g(a) } else { !true } }\nlet x = f(1, { 2 - 3 });";
+        let stmts = parse_stmts(source).unwrap();
+        let mut arena = Arena::new();
+        let roots: Vec<_> = stmts.iter().map(|s| arena.import_stmt(s)).collect();
+        let back: Vec<_> = roots.iter().map(|r| arena.export_stmt(*r)).collect();
+        assert_eq!(back, stmts);
+        for (before, after) in stmts.iter().zip(&back) {
+            assert_eq!((before.id, before.span), (after.id, after.span));
+        }
+    }
+
+    #[test]
+    fn test_identity_fold_allocates_nothing() {
+        let (mut arena, roots) = import("let x = (1 + y) - f(2, z); fn g(p) { p }");
+        let size = arena.len();
+        for root in &roots {
+            assert_eq!(Identity.fold_stmt(&mut arena, *root), *root);
+        }
+        assert_eq!(arena.len(), size);
+    }
+
+    #[test]
+    fn test_fold_shares_unchanged_subtrees() {
+        let (mut arena, roots) = import("let x = f(a - b) - (1 + 2);");
+        let StmtKind::Let(_, value) = arena.stmt(roots[0]).kind else { panic!("Expected let") };
+        let ExprKind::Sub(call, sum) = arena.expr(value).kind else { panic!("Expected -") };
+        let size = arena.len();
+
+        let folded = AddFolder.fold_stmt(&mut arena, roots[0]);
+        assert_ne!(folded, roots[0]);
+        // one new node each for the sum, the subtraction and the statement
+        assert_eq!(arena.len(), size + 3);
+        let StmtKind::Let(_, new_value) = arena.stmt(folded).kind else { panic!("Expected let") };
+        let ExprKind::Sub(new_call, new_sum) = arena.expr(new_value).kind else {
+            panic!("Expected -")
+        };
+        assert_eq!(new_call, call);
+        assert_eq!(arena.expr(new_sum).id, arena.expr(sum).id);
+        assert_eq!(arena.export_stmt(folded).to_string(), "let x = f(a - b) - 3;");
+        assert_eq!(arena.export_stmt(roots[0]).to_string(), "let x = f(a - b) - (1 + 2);");
+    }
+
+    #[test]
+    fn test_fold_rebuilds_lists_around_changed_children() {
+        let (mut arena, roots) = import("fn g(p) { let q = p; h(p, 3 + 4, q) }");
+        let size = arena.len();
+        let folded = AddFolder.fold_stmt(&mut arena, roots[0]);
+        // the sum, the call, the block, the fn and the statement; `let q = p;` is shared
+        assert_eq!(arena.len(), size + 5);
+        assert_eq!(
+            arena.export_stmt(folded).to_string(),
+            "fn g(p) {\n    let q = p;\n    h(p, 7, q)\n}"
+        );
+        let body = |arena: &Arena, r: StmtRef| {
+            let StmtKind::Fn(d) = arena.stmt(r).kind else { panic!("Expected fn") };
+            arena.fn_def(d).kind.body
+        };
+        let (old, new) = (body(&arena, roots[0]), body(&arena, folded));
+        assert_eq!(arena.block(old).kind.stmts, arena.block(new).kind.stmts);
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::ast::{
+    Block, CmpOp, Expr, ExprKind, FnDef, Name, NodeId, NodeIds, Span, Stmt, StmtKind,
+};
+use super::node_map::NodeMap;
+use super::{super_fold_block, super_fold_expr, Folder, VisitMut};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ty {
+    Int,
+    Bool,
+    Unit,
+    Unknown,
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Ty::Int => "int",
+            Ty::Bool => "bool",
+            Ty::Unit => "()",
+            Ty::Unknown => "_",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    UseBeforeDefinition { name: String },
+    DuplicateBinding { name: String, previous: Option<Span> },
+    TypeMismatch { expected: Ty, found: Ty },
+    ArityMismatch { name: String, expected: usize, found: usize },
+    NotAFunction { name: String },
+    NotAValue { name: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub node: NodeId,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{}:{}: ", span.line, span.column)?;
+        }
+        match &self.kind {
+            DiagnosticKind::UseBeforeDefinition { name } => {
+                write!(f, "'{}' is used before it is defined", name)
+            }
+            DiagnosticKind::DuplicateBinding { name, previous: Some(previous) } => write!(
+                f,
+                "'{}' is already bound in this scope (at {}:{})",
+                name, previous.line, previous.column
+            ),
+            DiagnosticKind::DuplicateBinding { name, previous: None } => {
+                write!(f, "'{}' is already bound in this scope", name)
+            }
+            DiagnosticKind::TypeMismatch { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            DiagnosticKind::ArityMismatch { name, expected, found } => {
+                write!(f, "'{}' takes {} argument(s) but {} were given", name, expected, found)
+            }
+            DiagnosticKind::NotAFunction { name } => write!(f, "'{}' is not a function", name),
+            DiagnosticKind::NotAValue { name } => {
+                write!(f, "function '{}' cannot be used as a value", name)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Binding {
+    Value(Ty),
+    Fn { arity: usize, ret: Ty },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    binding: Binding,
+    span: Option<Span>,
+}
+
+// The checker is a Folder that hands every node back untouched: the
+// traversal order of super_fold_* gives it definition order for free, and
+// node ids survive the fold, so `types` can be queried against the output.
+// `check` numbers its input first. Hand-built nodes all carry NodeId::DUMMY
+// and separate parses reuse the same ids, and either would make one node's
+// type stand in for another's.
+pub struct Checker {
+    scopes: Vec<HashMap<String, Entry>>,
+    ids: NodeIds,
+    pub types: NodeMap<Ty>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Checker {
+            scopes: vec![HashMap::new()],
+            ids: NodeIds::new(),
+            types: NodeMap::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn with_global(mut self, name: impl Into<String>, ty: Ty) -> Self {
+        let entry = Entry { binding: Binding::Value(ty), span: None };
+        self.scopes[0].insert(name.into(), entry);
+        self
+    }
+
+    pub fn check(&mut self, mut stmts: Vec<Box<Stmt>>) -> Vec<Box<Stmt>> {
+        for stmt in &mut stmts {
+            self.ids.visit_stmt_mut(stmt);
+        }
+        self.scopes.push(HashMap::new());
+        let stmts = stmts.into_iter().map(|s| self.fold_stmt(s)).collect();
+        self.scopes.pop();
+        stmts
+    }
+
+    pub fn type_of(&self, id: NodeId) -> Ty {
+        self.types.get(id).copied().unwrap_or(Ty::Unknown)
+    }
+
+    fn report(&mut self, kind: DiagnosticKind, node: NodeId, span: Option<Span>) {
+        self.diagnostics.push(Diagnostic { kind, node, span });
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).map(|e| e.binding)
+    }
+
+    fn bind(&mut self, name: &Name, binding: Binding) {
+        let entry = Entry { binding, span: name.span };
+        let scope = self.scopes.last_mut().unwrap();
+        if let Some(previous) = scope.insert(name.value.clone(), entry) {
+            let kind = DiagnosticKind::DuplicateBinding {
+                name: name.value.clone(),
+                previous: previous.span,
+            };
+            self.report(kind, name.id, name.span);
+        }
+    }
+
+    fn expect(&mut self, node: NodeId, span: Option<Span>, expected: Ty) {
+        let found = self.type_of(node);
+        if found != expected && found != Ty::Unknown && expected != Ty::Unknown {
+            self.report(DiagnosticKind::TypeMismatch { expected, found }, node, span);
+        }
+    }
+
+    fn expect_expr(&mut self, e: &Expr, expected: Ty) {
+        self.expect(e.id, e.span, expected);
+    }
+
+    fn infer(&mut self, e: &Expr) -> Ty {
+        match &e.kind {
+            ExprKind::IntLit(_) => Ty::Int,
+            ExprKind::BoolLit(_) => Ty::Bool,
+            ExprKind::Var(n) => match self.lookup(&n.value) {
+                Some(Binding::Value(ty)) => ty,
+                Some(Binding::Fn { .. }) => {
+                    self.report(DiagnosticKind::NotAValue { name: n.value.clone() }, n.id, n.span);
+                    Ty::Unknown
+                }
+                None => {
+                    let kind = DiagnosticKind::UseBeforeDefinition { name: n.value.clone() };
+                    self.report(kind, n.id, n.span);
+                    Ty::Unknown
+                }
+            },
+            ExprKind::Add(left, right) | ExprKind::Sub(left, right) => {
+                self.expect_expr(left, Ty::Int);
+                self.expect_expr(right, Ty::Int);
+                Ty::Int
+            }
+            ExprKind::Cmp(CmpOp::Eq | CmpOp::Ne, left, right) => {
+                let ty = self.type_of(left.id);
+                self.expect_expr(right, ty);
+                Ty::Bool
+            }
+            ExprKind::Cmp(_, left, right) => {
+                self.expect_expr(left, Ty::Int);
+                self.expect_expr(right, Ty::Int);
+                Ty::Bool
+            }
+            ExprKind::And(left, right) | ExprKind::Or(left, right) => {
+                self.expect_expr(left, Ty::Bool);
+                self.expect_expr(right, Ty::Bool);
+                Ty::Bool
+            }
+            ExprKind::Not(inner) => {
+                self.expect_expr(inner, Ty::Bool);
+                Ty::Bool
+            }
+            ExprKind::Block(b) => self.type_of(b.id),
+            ExprKind::If(cond, then, els) => {
+                self.expect_expr(cond, Ty::Bool);
+                let ty = self.type_of(then.id);
+                match els {
+                    Some(els) => {
+                        self.expect(els.id, els.span, ty);
+                        if ty == Ty::Unknown { self.type_of(els.id) } else { ty }
+                    }
+                    None => {
+                        self.expect(then.id, then.span, Ty::Unit);
+                        Ty::Unit
+                    }
+                }
+            }
+            ExprKind::Call(n, args) => match self.lookup(&n.value) {
+                Some(Binding::Fn { arity, ret }) => {
+                    if arity != args.len() {
+                        let kind = DiagnosticKind::ArityMismatch {
+                            name: n.value.clone(),
+                            expected: arity,
+                            found: args.len(),
+                        };
+                        self.report(kind, e.id, e.span);
+                    }
+                    ret
+                }
+                Some(Binding::Value(_)) => {
+                    let kind = DiagnosticKind::NotAFunction { name: n.value.clone() };
+                    self.report(kind, n.id, n.span);
+                    Ty::Unknown
+                }
+                None => {
+                    let kind = DiagnosticKind::UseBeforeDefinition { name: n.value.clone() };
+                    self.report(kind, n.id, n.span);
+                    Ty::Unknown
+                }
+            },
+        }
+    }
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Checker::new()
+    }
+}
+
+impl Folder for Checker {
+    fn fold_expr(&mut self, e: Box<Expr>) -> Box<Expr> {
+        let e = super_fold_expr(self, e);
+        let ty = self.infer(&e);
+        self.types.insert(e.id, ty);
+        e
+    }
+
+    fn fold_stmt(&mut self, s: Box<Stmt>) -> Box<Stmt> {
+        let Stmt { id, span, kind } = *s;
+        let kind = match kind {
+            StmtKind::Expr(e) => StmtKind::Expr(self.fold_expr(e)),
+            StmtKind::Let(n, e) => {
+                let e = self.fold_expr(e);
+                self.bind(&n, Binding::Value(self.type_of(e.id)));
+                StmtKind::Let(n, e)
+            }
+            StmtKind::Fn(d) => StmtKind::Fn(self.fold_fn_def(d)),
+        };
+        Box::new(Stmt { id, span, kind })
+    }
+
+    fn fold_block(&mut self, b: Box<Block>) -> Box<Block> {
+        self.scopes.push(HashMap::new());
+        let b = super_fold_block(self, b);
+        self.scopes.pop();
+        let ty = b.expr.as_ref().map_or(Ty::Unit, |e| self.type_of(e.id));
+        self.types.insert(b.id, ty);
+        b
+    }
+
+    fn fold_fn_def(&mut self, d: Box<FnDef>) -> Box<FnDef> {
+        let arity = d.params.len();
+        self.bind(&d.name, Binding::Fn { arity, ret: Ty::Unknown });
+        self.scopes.push(HashMap::new());
+        for param in &d.params {
+            self.bind(param, Binding::Value(Ty::Unknown));
+        }
+        let FnDef { id, span, name, params, body } = *d;
+        let body = self.fold_block(body);
+        self.scopes.pop();
+        let ret = self.type_of(body.id);
+        if let Some(entry) = self.scopes.last_mut().unwrap().get_mut(&name.value) {
+            entry.binding = Binding::Fn { arity, ret };
+        }
+        Box::new(FnDef { id, span, name, params, body })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::parse_stmts;
+    use super::*;
+
+    fn checker() -> Checker {
+        Checker::new().with_global("input", Ty::Int)
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        let mut checker = checker();
+        checker.check(parse_stmts(source).unwrap());
+        checker.diagnostics.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_clean_program_has_no_diagnostics() {
+        let source = "fn max(a, b) { if a > b { a } else { b } }\n\
+                      let x = max(input, 3);\n\
+                      let x_ok = x == 3 || { let y = x + 1; y < 10 };";
+        let mut checker = checker();
+        let stmts = checker.check(parse_stmts(source).unwrap());
+        assert_eq!(checker.diagnostics, vec![]);
+        let StmtKind::Let(_, value) = &stmts[2].kind else { panic!("Expected let") };
+        assert_eq!(checker.type_of(value.id), Ty::Bool);
+    }
+
+    #[test]
+    fn test_use_before_definition() {
+        let source = "let a = b + 1;\nlet b = 2;\nf(b);\nfn f(x) { x }";
+        assert_eq!(
+            messages(source),
+            vec![
+                "1:9: 'b' is used before it is defined",
+                "3:1: 'f' is used before it is defined",
+            ]
+        );
+        assert!(messages("fn fact(n) { if n < 2 { 1 } else { fact(n - 1) } }").is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_bindings_are_scope_local() {
+        let source = "let x = 1;\nlet x = 2;\nfn f(a, a) { let x = a; x }";
+        assert_eq!(
+            messages(source),
+            vec![
+                "2:5: 'x' is already bound in this scope (at 1:5)",
+                "3:9: 'a' is already bound in this scope (at 3:6)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hand_built_tree_once_numbered() {
+        use super::super::ast::NodeIds;
+        use super::super::VisitMut;
+
+        let int = |v| Box::new(Expr::new(ExprKind::IntLit(v)));
+        let name = |n: &str| Box::new(Name::new(n));
+        let sum = Box::new(Expr::new(ExprKind::Add(int(1), int(2))));
+        let var = Box::new(Expr::new(ExprKind::Var(name("x"))));
+        let mut stmts = vec![
+            Box::new(Stmt::new(StmtKind::Let(name("x"), sum))),
+            Box::new(Stmt::new(StmtKind::Expr(Box::new(Expr::new(ExprKind::Not(var)))))),
+        ];
+        let mut ids = NodeIds::new();
+        for stmt in &mut stmts {
+            ids.visit_stmt_mut(stmt);
+        }
+        let mut checker = checker();
+        let stmts = checker.check(stmts);
+        let StmtKind::Let(_, value) = &stmts[0].kind else { panic!("Expected let") };
+        assert_eq!(checker.type_of(value.id), Ty::Int);
+        let StmtKind::Expr(not) = &stmts[1].kind else { panic!("Expected expression") };
+        let ExprKind::Not(var) = &not.kind else { panic!("Expected !") };
+        let found = (checker.diagnostics.len(), checker.diagnostics[0].node);
+        assert_eq!(found, (1, var.id));
+        assert_eq!(checker.type_of(not.id), Ty::Bool);
+    }
+
+    #[test]
+    fn test_hand_built_mismatches_are_found() {
+        let expr = |kind| Box::new(Expr::new(kind));
+        let stmt = |e| Box::new(Stmt::new(StmtKind::Expr(e)));
+        let (t, one) = (|| expr(ExprKind::BoolLit(true)), || expr(ExprKind::IntLit(1)));
+        let then = Box::new(Block::new(Vec::new(), Some(one())));
+        let stmts = vec![
+            stmt(expr(ExprKind::Add(t(), one()))),
+            stmt(expr(ExprKind::Cmp(CmpOp::Lt, one(), t()))),
+            stmt(expr(ExprKind::If(one(), then, None))),
+        ];
+        let mut checker = checker();
+        checker.check(stmts);
+        let messages: Vec<_> = checker.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "expected int, found bool",
+                "expected int, found bool",
+                "expected bool, found int",
+                "expected (), found int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_separate_parses_are_checked_together() {
+        let mut stmts = parse_stmts("let a = true;").unwrap();
+        stmts.extend(parse_stmts("let b = 1 + 2;").unwrap());
+        let mut checker = checker();
+        let stmts = checker.check(stmts);
+        assert!(checker.diagnostics.is_empty());
+        let types: Vec<_> = stmts
+            .iter()
+            .map(|s| match &s.kind {
+                StmtKind::Let(_, value) => checker.type_of(value.id),
+                _ => panic!("Expected let"),
+            })
+            .collect();
+        assert_eq!(types, [Ty::Bool, Ty::Int]);
+    }
+
+    #[test]
+    fn test_type_errors_and_call_checks() {
+        let source = "fn one() { 1 }\nlet t = !1 + true;\none(2);\n\
+                      let v = 3;\nv();\nif t { 1 };";
+        assert_eq!(
+            messages(source),
+            vec![
+                "2:10: expected bool, found int",
+                "2:9: expected int, found bool",
+                "2:14: expected int, found bool",
+                "3:1: 'one' takes 0 argument(s) but 1 were given",
+                "5:1: 'v' is not a function",
+                "6:4: expected bool, found int",
+                "6:6: expected (), found int",
+            ]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use super::ast::{Block, CmpOp, Expr, ExprKind, FnDef, Name, Span, Stmt, StmtKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Unit,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Unit => f.write_str("()"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalErrorKind {
+    Unbound(String),
+    TypeMismatch { expected: &'static str, found: Value },
+    Overflow,
+    ArityMismatch { name: String, expected: usize, found: usize },
+    NotAFunction(String),
+    NotAValue(String),
+    RecursionLimit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{}:{}: ", span.line, span.column)?;
+        }
+        match &self.kind {
+            EvalErrorKind::Unbound(name) => write!(f, "unbound name '{}'", name),
+            EvalErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            EvalErrorKind::Overflow => f.write_str("arithmetic overflow"),
+            EvalErrorKind::ArityMismatch { name, expected, found } => {
+                write!(f, "'{}' takes {} argument(s) but {} were given", name, expected, found)
+            }
+            EvalErrorKind::NotAFunction(name) => write!(f, "'{}' is not a function", name),
+            EvalErrorKind::NotAValue(name) => {
+                write!(f, "function '{}' cannot be used as a value", name)
+            }
+            EvalErrorKind::RecursionLimit => f.write_str("recursion limit reached"),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+#[derive(Debug, Clone)]
+struct Closure {
+    def: Box<FnDef>,
+    captured: Environment,
+}
+
+#[derive(Debug, Clone)]
+enum Binding {
+    Value(Value),
+    Fn(Rc<Closure>),
+}
+
+// Frames are shared copy-on-write: closures capture the environment by cloning a handful of
+// `Rc`s, and a frame is only copied when it is written to while a closure still holds it.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    frames: Vec<Rc<HashMap<String, Binding>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment { frames: vec![Rc::default()] }
+    }
+
+    pub fn with(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: Value) {
+        self.frame().insert(name.into(), Binding::Value(value));
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        match self.binding(name)? {
+            Binding::Value(value) => Some(*value),
+            Binding::Fn(_) => None,
+        }
+    }
+
+    pub fn values(&self) -> Vec<(&str, Value)> {
+        let mut values: Vec<_> = self.frames[0]
+            .iter()
+            .filter_map(|(name, binding)| match binding {
+                Binding::Value(value) => Some((name.as_str(), *value)),
+                Binding::Fn(_) => None,
+            })
+            .collect();
+        values.sort_by_key(|(name, _)| *name);
+        values
+    }
+
+    fn binding(&self, name: &str) -> Option<&Binding> {
+        self.frames.iter().rev().find_map(|frame| frame.get(name))
+    }
+
+    fn bind(&mut self, name: &Name, binding: Binding) {
+        self.frame().insert(name.value.clone(), binding);
+    }
+
+    fn frame(&mut self) -> &mut HashMap<String, Binding> {
+        Rc::make_mut(self.frames.last_mut().unwrap())
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::new()
+    }
+}
+
+pub struct Evaluator {
+    env: Environment,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Evaluator {
+    pub fn new(env: Environment) -> Self {
+        Evaluator { env, depth: 0, max_depth: 64 }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.env
+    }
+
+    pub fn into_environment(self) -> Environment {
+        self.env
+    }
+
+    pub fn run<'a>(
+        &mut self,
+        stmts: impl IntoIterator<Item = &'a Stmt>,
+    ) -> Result<Vec<Value>, EvalError> {
+        let mut values = Vec::new();
+        for stmt in stmts {
+            if let Some(value) = self.exec(stmt)? {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> Result<Option<Value>, EvalError> {
+        match &stmt.kind {
+            StmtKind::Expr(e) => self.eval(e).map(Some),
+            StmtKind::Let(n, e) => {
+                let value = self.eval(e)?;
+                self.env.bind(n, Binding::Value(value));
+                Ok(None)
+            }
+            StmtKind::Fn(d) => {
+                let captured = self.env.clone();
+                let closure = Closure { def: d.clone(), captured };
+                self.env.bind(&d.name, Binding::Fn(Rc::new(closure)));
+                Ok(None)
+            }
+        }
+    }
+
+    fn block(&mut self, b: &Block) -> Result<Value, EvalError> {
+        let depth = self.env.frames.len();
+        self.env.frames.push(Rc::default());
+        let result = self.block_body(b);
+        self.env.frames.truncate(depth);
+        result
+    }
+
+    fn block_body(&mut self, b: &Block) -> Result<Value, EvalError> {
+        for stmt in &b.stmts {
+            self.exec(stmt)?;
+        }
+        b.expr.as_ref().map_or(Ok(Value::Unit), |e| self.eval(e))
+    }
+
+    fn int(&mut self, e: &Expr) -> Result<i64, EvalError> {
+        match self.eval(e)? {
+            Value::Int(v) => Ok(v),
+            found => Err(error(EvalErrorKind::TypeMismatch { expected: "int", found }, e.span)),
+        }
+    }
+
+    fn bool(&mut self, e: &Expr) -> Result<bool, EvalError> {
+        match self.eval(e)? {
+            Value::Bool(b) => Ok(b),
+            found => Err(error(EvalErrorKind::TypeMismatch { expected: "bool", found }, e.span)),
+        }
+    }
+
+    fn eval(&mut self, e: &Expr) -> Result<Value, EvalError> {
+        let overflow = || error(EvalErrorKind::Overflow, e.span);
+        Ok(match &e.kind {
+            ExprKind::IntLit(v) => Value::Int(*v),
+            ExprKind::BoolLit(b) => Value::Bool(*b),
+            ExprKind::Var(n) => match self.env.binding(&n.value) {
+                Some(Binding::Value(value)) => *value,
+                Some(Binding::Fn(_)) => {
+                    return Err(error(EvalErrorKind::NotAValue(n.value.clone()), n.span));
+                }
+                None => return Err(error(EvalErrorKind::Unbound(n.value.clone()), n.span)),
+            },
+            ExprKind::Add(left, right) => {
+                let (a, b) = (self.int(left)?, self.int(right)?);
+                Value::Int(a.checked_add(b).ok_or_else(overflow)?)
+            }
+            ExprKind::Sub(left, right) => {
+                let (a, b) = (self.int(left)?, self.int(right)?);
+                Value::Int(a.checked_sub(b).ok_or_else(overflow)?)
+            }
+            ExprKind::Cmp(op @ (CmpOp::Eq | CmpOp::Ne), left, right) => {
+                let a = self.eval(left)?;
+                let b = self.eval(right)?;
+                if mem::discriminant(&a) != mem::discriminant(&b) {
+                    let expected = match a {
+                        Value::Int(_) => "int",
+                        Value::Bool(_) => "bool",
+                        Value::Unit => "()",
+                    };
+                    let kind = EvalErrorKind::TypeMismatch { expected, found: b };
+                    return Err(error(kind, right.span));
+                }
+                Value::Bool((a == b) == (*op == CmpOp::Eq))
+            }
+            ExprKind::Cmp(op, left, right) => {
+                let (a, b) = (self.int(left)?, self.int(right)?);
+                Value::Bool(op.apply(a, b))
+            }
+            ExprKind::And(left, right) => Value::Bool(self.bool(left)? && self.bool(right)?),
+            ExprKind::Or(left, right) => Value::Bool(self.bool(left)? || self.bool(right)?),
+            ExprKind::Not(inner) => Value::Bool(!self.bool(inner)?),
+            ExprKind::Block(b) => self.block(b)?,
+            ExprKind::If(cond, then, els) => {
+                if self.bool(cond)? {
+                    let value = self.block(then)?;
+                    // Like the checker, an `if` without `else` must not produce a value.
+                    if els.is_none() && value != Value::Unit {
+                        let kind = EvalErrorKind::TypeMismatch { expected: "()", found: value };
+                        return Err(error(kind, then.span));
+                    }
+                    value
+                } else if let Some(els) = els {
+                    self.block(els)?
+                } else {
+                    Value::Unit
+                }
+            }
+            ExprKind::Call(n, args) => self.call(e, n, args)?,
+        })
+    }
+
+    fn call(&mut self, e: &Expr, n: &Name, args: &[Box<Expr>]) -> Result<Value, EvalError> {
+        let closure = match self.env.binding(&n.value) {
+            Some(Binding::Fn(closure)) => Rc::clone(closure),
+            Some(Binding::Value(_)) => {
+                return Err(error(EvalErrorKind::NotAFunction(n.value.clone()), n.span));
+            }
+            None => return Err(error(EvalErrorKind::Unbound(n.value.clone()), n.span)),
+        };
+        let def = &closure.def;
+        if def.params.len() != args.len() {
+            let kind = EvalErrorKind::ArityMismatch {
+                name: n.value.clone(),
+                expected: def.params.len(),
+                found: args.len(),
+            };
+            return Err(error(kind, e.span));
+        }
+        if self.depth >= self.max_depth {
+            return Err(error(EvalErrorKind::RecursionLimit, e.span));
+        }
+        let args = args.iter().map(|a| self.eval(a)).collect::<Result<Vec<_>, _>>()?;
+
+        let mut env = closure.captured.clone();
+        env.frames.push(Rc::default());
+        env.bind(&def.name, Binding::Fn(Rc::clone(&closure)));
+        for (param, value) in def.params.iter().zip(args) {
+            env.bind(param, Binding::Value(value));
+        }
+        let saved = mem::replace(&mut self.env, env);
+        self.depth += 1;
+        let result = self.block(&def.body);
+        self.depth -= 1;
+        self.env = saved;
+        result
+    }
+}
+
+fn error(kind: EvalErrorKind, span: Option<Span>) -> EvalError {
+    EvalError { kind, span }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::parse_stmts;
+    use super::*;
+
+    fn run(source: &str, env: Environment) -> Result<(Vec<Value>, Environment), EvalError> {
+        let stmts = parse_stmts(source).unwrap();
+        let mut evaluator = Evaluator::new(env);
+        let values = evaluator.run(stmts.iter().map(|s| &**s))?;
+        Ok((values, evaluator.into_environment()))
+    }
+
+    #[test]
+    fn test_runs_statements_against_environment() {
+        let source = "let y = x + 1;\n\
+                      fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\n\
+                      fib(y);\n\
+                      let big = { let t = fib(10); t > 50 && !false };\n\
+                      y == 5;";
+        let (values, env) = run(source, Environment::new().with("x", Value::Int(4))).unwrap();
+        assert_eq!(values, vec![Value::Int(5), Value::Bool(true)]);
+        assert_eq!(
+            env.values(),
+            vec![("big", Value::Bool(true)), ("x", Value::Int(4)), ("y", Value::Int(5))]
+        );
+        assert_eq!(env.lookup("t"), None);
+        assert_eq!(env.lookup("fib"), None);
+    }
+
+    #[test]
+    fn test_closures_capture_definition_scope() {
+        let source = "let k = 10;\nfn add_k(v) { v + k }\nlet k = 0;\nadd_k(1);\nk;";
+        let (values, _) = run(source, Environment::new()).unwrap();
+        assert_eq!(values, vec![Value::Int(11), Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_runtime_errors_carry_spans() {
+        let err = |source: &str| run(source, Environment::new()).unwrap_err().to_string();
+        assert_eq!(err("let a = 1;\nb;"), "2:1: unbound name 'b'");
+        assert_eq!(err("1 + true;"), "1:5: expected int, found true");
+        assert_eq!(err("9223372036854775807 + 1;"), "1:1: arithmetic overflow");
+        assert_eq!(err("fn f(a) { a }\nf();"), "2:1: 'f' takes 1 argument(s) but 0 were given");
+        assert_eq!(err("false || 1;"), "1:10: expected bool, found 1");
+        assert_eq!(err("fn f() { f() }\nf();"), "1:10: recursion limit reached");
+        assert_eq!(err("if true { 1 };"), "1:9: expected (), found 1");
+        assert!(run("if true { let a = 1; };", Environment::new()).is_ok());
+        // short-circuiting never evaluates the unbound right-hand side
+        assert!(run("false && missing;", Environment::new()).is_ok());
+    }
+
+    #[test]
+    fn test_captured_frames_are_shared_until_written() {
+        let mut env = Environment::new().with("k", Value::Int(1));
+        let captured = env.clone();
+        assert!(Rc::ptr_eq(&captured.frames[0], &env.frames[0]));
+        env.set("k", Value::Int(2));
+        assert!(!Rc::ptr_eq(&captured.frames[0], &env.frames[0]));
+        assert_eq!(captured.lookup("k"), Some(Value::Int(1)));
+        assert_eq!(env.lookup("k"), Some(Value::Int(2)));
+    }
+}
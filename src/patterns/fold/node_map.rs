@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use super::ast::{Block, Expr, FnDef, Name, NodeId, Span, Stmt};
+use super::{walk_block, walk_expr, walk_fn_def, walk_stmt, Visit};
+
+#[derive(Debug, Clone)]
+pub struct NodeMap<T> {
+    entries: HashMap<NodeId, T>,
+}
+
+impl<T> NodeMap<T> {
+    pub fn new() -> Self {
+        NodeMap { entries: HashMap::new() }
+    }
+
+    // Every hand-built node carries NodeId::DUMMY, so accepting it would quietly merge their
+    // entries into one. Such trees have to be numbered with `ast::NodeIds` first.
+    pub fn insert(&mut self, id: NodeId, value: T) -> Option<T> {
+        assert!(id != NodeId::DUMMY, "NodeMap keyed by NodeId::DUMMY; number the tree first");
+        self.entries.insert(id, value)
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.entries.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.entries.get_mut(&id)
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.entries.contains_key(&id)
+    }
+
+    pub fn remove(&mut self, id: NodeId) -> Option<T> {
+        self.entries.remove(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.entries.iter().map(|(id, value)| (*id, value))
+    }
+}
+
+impl<T> Default for NodeMap<T> {
+    fn default() -> Self {
+        NodeMap::new()
+    }
+}
+
+impl<T> FromIterator<(NodeId, T)> for NodeMap<T> {
+    fn from_iter<I: IntoIterator<Item = (NodeId, T)>>(iter: I) -> Self {
+        let mut map = NodeMap::new();
+        for (id, value) in iter {
+            map.insert(id, value);
+        }
+        map
+    }
+}
+
+#[derive(Default)]
+struct SpanCollector(NodeMap<Span>);
+
+impl SpanCollector {
+    fn record(&mut self, id: NodeId, span: Option<Span>) {
+        if let Some(span) = span.filter(|_| id != NodeId::DUMMY) {
+            self.0.insert(id, span);
+        }
+    }
+}
+
+impl Visit for SpanCollector {
+    fn visit_name(&mut self, n: &Name) {
+        self.record(n.id, n.span);
+    }
+
+    fn visit_expr(&mut self, e: &Expr) {
+        self.record(e.id, e.span);
+        walk_expr(self, e);
+    }
+
+    fn visit_stmt(&mut self, s: &Stmt) {
+        self.record(s.id, s.span);
+        walk_stmt(self, s);
+    }
+
+    fn visit_block(&mut self, b: &Block) {
+        self.record(b.id, b.span);
+        walk_block(self, b);
+    }
+
+    fn visit_fn_def(&mut self, d: &FnDef) {
+        self.record(d.id, d.span);
+        walk_fn_def(self, d);
+    }
+}
+
+pub fn spans<'a>(stmts: impl IntoIterator<Item = &'a Stmt>) -> NodeMap<Span> {
+    let mut collector = SpanCollector::default();
+    for stmt in stmts {
+        collector.visit_stmt(stmt);
+    }
+    collector.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ast::{ExprKind, NodeIds, StmtKind};
+    use super::super::parser::parse_stmts;
+    use super::super::VisitMut;
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    enum Ty {
+        Int,
+        Bool,
+    }
+
+    struct Typer(NodeMap<Ty>);
+
+    impl Visit for Typer {
+        fn visit_expr(&mut self, e: &Expr) {
+            walk_expr(self, e);
+            let ty = match &e.kind {
+                ExprKind::IntLit(_) | ExprKind::Add(..) | ExprKind::Sub(..) => Ty::Int,
+                ExprKind::BoolLit(_) | ExprKind::Cmp(..) | ExprKind::Not(_) => Ty::Bool,
+                _ => return,
+            };
+            self.0.insert(e.id, ty);
+        }
+    }
+
+    #[test]
+    fn test_analyses_attach_data_by_id() {
+        let source = "let x = 1 + 2 < 4;\ny;";
+        let stmts = parse_stmts(source).unwrap();
+        let mut typer = Typer(NodeMap::new());
+        for stmt in &stmts {
+            typer.visit_stmt(stmt);
+        }
+        let spans = spans(stmts.iter().map(|s| &**s));
+        let mut typed: Vec<_> = typer
+            .0
+            .iter()
+            .map(|(id, ty)| {
+                let span = spans.get(id).unwrap();
+                (&source[span.start..span.end], *ty)
+            })
+            .collect();
+        typed.sort();
+        assert_eq!(
+            typed,
+            vec![
+                ("1", Ty::Int),
+                ("1 + 2", Ty::Int),
+                ("1 + 2 < 4", Ty::Bool),
+                ("2", Ty::Int),
+                ("4", Ty::Int),
+            ]
+        );
+        assert_eq!(spans.get(stmts[1].id).map(|s| s.line), Some(2));
+    }
+
+    #[test]
+    fn test_hand_built_nodes_are_numbered_on_request() {
+        let e = Expr::new(ExprKind::IntLit(1));
+        let other = Expr::new(ExprKind::IntLit(1));
+        assert_eq!((e.id, other.id), (NodeId::DUMMY, NodeId::DUMMY));
+        assert_eq!(e, other);
+        let mut stmt = Stmt::new(StmtKind::Expr(Box::new(e)));
+        assert!(spans([&stmt]).is_empty());
+
+        let mut ids = NodeIds::new();
+        ids.visit_stmt_mut(&mut stmt);
+        let StmtKind::Expr(e) = &stmt.kind else { panic!("Expected expression statement") };
+        assert_eq!((stmt.id.as_u32(), e.id.as_u32()), (0, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "NodeId::DUMMY")]
+    fn test_dummy_ids_are_rejected() {
+        let mut types = NodeMap::new();
+        types.insert(Expr::new(ExprKind::IntLit(1)).id, Ty::Int);
+    }
+
+    #[test]
+    fn test_ids_are_per_parse_and_deterministic() {
+        let source = "let a = 1 + 2;";
+        let first = parse_stmts(source).unwrap();
+        let spans_of = |stmts: &[Box<Stmt>]| spans(stmts.iter().map(|s| &**s));
+        let mut ids: Vec<_> = spans_of(&first).iter().map(|(id, _)| id.as_u32()).collect();
+        ids.sort();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+        let second = parse_stmts(source).unwrap();
+        assert_eq!(spans_of(&second).len(), 5);
+        assert_eq!(second[0].id, first[0].id);
+    }
+
+    #[test]
+    fn test_clones_alias_until_renumbered() {
+        let mut ids = NodeIds::new();
+        let mut original = Stmt::new(StmtKind::Expr(Box::new(Expr::new(ExprKind::IntLit(7)))));
+        ids.visit_stmt_mut(&mut original);
+        let mut types = NodeMap::new();
+        types.insert(original.id, Ty::Int);
+
+        let mut copy = original.clone();
+        assert_eq!(copy.id, original.id);
+        assert_eq!(types.get(copy.id), Some(&Ty::Int));
+
+        ids.visit_stmt_mut(&mut copy);
+        assert_ne!(copy.id, original.id);
+        assert_eq!(types.get(copy.id), None);
+        assert_eq!(copy, original);
+    }
+}
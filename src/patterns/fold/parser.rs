@@ -0,0 +1,569 @@
+use std::error::Error;
+use std::fmt;
+
+use super::ast::{Block, CmpOp, Expr, ExprKind, FnDef, Name, NodeIds, Span, Stmt, StmtKind};
+use super::VisitMut;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedChar(char),
+    Expected { expected: &'static str, found: String },
+    NumberOutOfRange(String),
+    TooDeep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "unexpected character '{}'", c),
+            ParseErrorKind::Expected { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ParseErrorKind::NumberOutOfRange(text) => {
+                write!(f, "number '{}' is out of range", text)
+            }
+            ParseErrorKind::TooDeep => write!(f, "nested more than {} levels deep", MAX_DEPTH),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+pub const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Int(String),
+    Ident(String),
+    Punct(&'static str),
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Int(text) => format!("number '{}'", text),
+            Token::Ident(name) => format!("'{}'", name),
+            Token::Punct(p) => format!("'{}'", p),
+            Token::End => "end of input".to_string(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Ident(name) if name == keyword)
+    }
+}
+
+const PUNCTUATION: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "=", ";", ",", "(", ")", "{", "}", "<", ">", "!",
+];
+const KEYWORDS: &[&str] = &["let", "fn", "if", "else", "true", "false"];
+
+struct Lexer<'a> {
+    rest: &'a str,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer<'_> {
+    fn bump(&mut self, len: usize) -> &str {
+        let (taken, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        self.offset += len;
+        for c in taken.chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        taken
+    }
+
+    fn tokenize(mut self) -> Result<Vec<(Token, Span)>, ParseError> {
+        let mut tokens = Vec::new();
+        loop {
+            let ws = self.rest.len() - self.rest.trim_start().len();
+            self.bump(ws);
+            let (start, line, column) = (self.offset, self.line, self.column);
+            let Some(c) = self.rest.chars().next() else {
+                tokens.push((Token::End, Span { start, end: start, line, column }));
+                return Ok(tokens);
+            };
+            let token = if c.is_ascii_digit() {
+                let len = self.rest.find(|d: char| !d.is_ascii_digit()).unwrap_or(self.rest.len());
+                Token::Int(self.bump(len).to_string())
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let len = self
+                    .rest
+                    .find(|d: char| !(d.is_ascii_alphanumeric() || d == '_'))
+                    .unwrap_or(self.rest.len());
+                Token::Ident(self.bump(len).to_string())
+            } else if let Some(p) = PUNCTUATION.iter().find(|p| self.rest.starts_with(**p)) {
+                self.bump(p.len());
+                Token::Punct(p)
+            } else {
+                let kind = ParseErrorKind::UnexpectedChar(c);
+                return Err(ParseError { kind, line, column });
+            };
+            tokens.push((token, Span { start, end: self.offset, line, column }));
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Span)>,
+    index: usize,
+    last_end: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.index].0.clone();
+        if token != Token::End {
+            self.last_end = self.tokens[self.index].1.end;
+            self.index += 1;
+        }
+        token
+    }
+
+    fn at(&self, punct: &str) -> bool {
+        matches!(self.peek(), Token::Punct(p) if *p == punct)
+    }
+
+    fn start(&self) -> Span {
+        self.tokens[self.index].1
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+        Span { end: self.last_end.max(start.start), ..start }
+    }
+
+    fn node(&self, start: Span, kind: ExprKind) -> Box<Expr> {
+        Box::new(Expr::new(kind).with_span(self.span_from(start)))
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        let Span { line, column, .. } = self.start();
+        ParseError { kind, line, column }
+    }
+
+    fn expected(&self, expected: &'static str) -> ParseError {
+        self.error(ParseErrorKind::Expected { expected, found: self.peek().describe() })
+    }
+
+    fn expect(&mut self, punct: &str, expected: &'static str) -> Result<(), ParseError> {
+        if !self.at(punct) {
+            return Err(self.expected(expected));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    // Both the parser and every pass over its output recurse once per level of the tree, so
+    // parentheses, blocks, calls, `!` and `else if` chains are bounded before deep input can
+    // run the stack out.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.descend()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    // Operator chains such as `a + b + c` lean left, so every operator pushes the tree built so
+    // far one level deeper and counts towards the same limit.
+    fn operator(&mut self) -> Result<(), ParseError> {
+        self.descend()?;
+        self.advance();
+        Ok(())
+    }
+
+    fn descend(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeep));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<Box<Name>, ParseError> {
+        match self.peek() {
+            Token::Ident(name) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = Name::new(name.clone()).with_span(self.start());
+                self.advance();
+                Ok(Box::new(name))
+            }
+            _ => Err(self.expected("a name")),
+        }
+    }
+
+    fn stmt(&mut self) -> Result<Box<Stmt>, ParseError> {
+        let start = self.start();
+        if self.peek().is_keyword("fn") {
+            let kind = StmtKind::Fn(self.fn_def()?);
+            return Ok(Box::new(Stmt::new(kind).with_span(self.span_from(start))));
+        }
+        let kind = if self.peek().is_keyword("let") {
+            self.advance();
+            let name = self.name()?;
+            self.expect("=", "'='")?;
+            StmtKind::Let(name, self.expr()?)
+        } else {
+            StmtKind::Expr(self.expr()?)
+        };
+        self.expect(";", "';'")?;
+        Ok(Box::new(Stmt::new(kind).with_span(self.span_from(start))))
+    }
+
+    fn fn_def(&mut self) -> Result<Box<FnDef>, ParseError> {
+        let start = self.start();
+        self.advance();
+        let name = self.name()?;
+        self.expect("(", "'('")?;
+        let mut params = Vec::new();
+        while !self.at(")") {
+            params.push(self.name()?);
+            if !self.at(",") {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(")", "')'")?;
+        let body = self.block()?;
+        Ok(Box::new(FnDef::new(name, params, body).with_span(self.span_from(start))))
+    }
+
+    fn block(&mut self) -> Result<Box<Block>, ParseError> {
+        self.nested(Self::block_contents)
+    }
+
+    fn block_contents(&mut self) -> Result<Box<Block>, ParseError> {
+        let start = self.start();
+        self.expect("{", "'{'")?;
+        let mut stmts = Vec::new();
+        let mut expr = None;
+        while !self.at("}") {
+            if self.peek().is_keyword("let") || self.peek().is_keyword("fn") {
+                stmts.push(self.stmt()?);
+                continue;
+            }
+            let stmt_start = self.start();
+            let e = self.expr()?;
+            if self.at(";") {
+                self.advance();
+                let stmt = Stmt::new(StmtKind::Expr(e)).with_span(self.span_from(stmt_start));
+                stmts.push(Box::new(stmt));
+            } else if self.at("}") {
+                expr = Some(e);
+            } else {
+                return Err(self.expected("';' or '}'"));
+            }
+        }
+        self.advance();
+        Ok(Box::new(Block::new(stmts, expr).with_span(self.span_from(start))))
+    }
+
+    fn expr(&mut self) -> Result<Box<Expr>, ParseError> {
+        let (start, outer) = (self.start(), self.depth);
+        let mut left = self.and()?;
+        while self.at("||") {
+            self.operator()?;
+            let right = self.and()?;
+            left = self.node(start, ExprKind::Or(left, right));
+        }
+        self.depth = outer;
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Box<Expr>, ParseError> {
+        let (start, outer) = (self.start(), self.depth);
+        let mut left = self.comparison()?;
+        while self.at("&&") {
+            self.operator()?;
+            let right = self.comparison()?;
+            left = self.node(start, ExprKind::And(left, right));
+        }
+        self.depth = outer;
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Box<Expr>, ParseError> {
+        let start = self.start();
+        let left = self.additive()?;
+        let op = match self.peek() {
+            Token::Punct("==") => CmpOp::Eq,
+            Token::Punct("!=") => CmpOp::Ne,
+            Token::Punct("<") => CmpOp::Lt,
+            Token::Punct("<=") => CmpOp::Le,
+            Token::Punct(">") => CmpOp::Gt,
+            Token::Punct(">=") => CmpOp::Ge,
+            _ => return Ok(left),
+        };
+        self.advance();
+        let right = self.additive()?;
+        Ok(self.node(start, ExprKind::Cmp(op, left, right)))
+    }
+
+    fn additive(&mut self) -> Result<Box<Expr>, ParseError> {
+        let (start, outer) = (self.start(), self.depth);
+        let mut left = self.unary()?;
+        loop {
+            if self.at("+") {
+                self.operator()?;
+                let right = self.unary()?;
+                left = self.node(start, ExprKind::Add(left, right));
+            } else if self.at("-") {
+                self.operator()?;
+                let right = self.unary()?;
+                left = self.node(start, ExprKind::Sub(left, right));
+            } else {
+                break;
+            }
+        }
+        self.depth = outer;
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Box<Expr>, ParseError> {
+        if self.at("!") {
+            let start = self.start();
+            self.advance();
+            let inner = self.nested(Self::unary)?;
+            return Ok(self.node(start, ExprKind::Not(inner)));
+        }
+        self.primary()
+    }
+
+    fn if_expr(&mut self) -> Result<Box<Expr>, ParseError> {
+        let start = self.start();
+        self.advance();
+        let cond = self.expr()?;
+        let then = self.block()?;
+        let els = if self.peek().is_keyword("else") {
+            self.advance();
+            if self.peek().is_keyword("if") {
+                let nested = self.nested(Self::if_expr)?;
+                let span = nested.span;
+                let mut block = Block::new(Vec::new(), Some(nested));
+                block.span = span;
+                Some(Box::new(block))
+            } else {
+                Some(self.block()?)
+            }
+        } else {
+            None
+        };
+        Ok(self.node(start, ExprKind::If(cond, then, els)))
+    }
+
+    fn primary(&mut self) -> Result<Box<Expr>, ParseError> {
+        let start = self.start();
+        let negative = self.at("-");
+        if negative {
+            self.advance();
+        }
+        match self.peek().clone() {
+            Token::Int(text) => {
+                let text = if negative { format!("-{}", text) } else { text };
+                let value = text
+                    .parse()
+                    .map_err(|_| self.error(ParseErrorKind::NumberOutOfRange(text)))?;
+                self.advance();
+                Ok(self.node(start, ExprKind::IntLit(value)))
+            }
+            _ if negative => Err(self.expected("a number")),
+            Token::Punct("(") => {
+                self.advance();
+                let inner = self.nested(Self::expr)?;
+                self.expect(")", "')'")?;
+                Ok(inner)
+            }
+            Token::Punct("{") => {
+                let block = self.block()?;
+                Ok(self.node(start, ExprKind::Block(block)))
+            }
+            ref t if t.is_keyword("if") => self.nested(Self::if_expr),
+            ref t if t.is_keyword("true") || t.is_keyword("false") => {
+                let value = t.is_keyword("true");
+                self.advance();
+                Ok(self.node(start, ExprKind::BoolLit(value)))
+            }
+            Token::Ident(_) => {
+                let name = self.name()?;
+                if !self.at("(") {
+                    return Ok(self.node(start, ExprKind::Var(name)));
+                }
+                self.advance();
+                let mut args = Vec::new();
+                while !self.at(")") {
+                    args.push(self.nested(Self::expr)?);
+                    if !self.at(",") {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(")", "')'")?;
+                Ok(self.node(start, ExprKind::Call(name, args)))
+            }
+            _ => Err(self.expected("an expression")),
+        }
+    }
+}
+
+fn parser(source: &str) -> Result<Parser, ParseError> {
+    let tokens = Lexer { rest: source, offset: 0, line: 1, column: 1 }.tokenize()?;
+    Ok(Parser { tokens, index: 0, last_end: 0, depth: 0 })
+}
+
+pub fn parse_expr(source: &str) -> Result<Box<Expr>, ParseError> {
+    let mut parser = parser(source)?;
+    let mut expr = parser.expr()?;
+    if *parser.peek() != Token::End {
+        return Err(parser.expected("end of input"));
+    }
+    NodeIds::new().visit_expr_mut(&mut expr);
+    Ok(expr)
+}
+
+pub fn parse_stmts(source: &str) -> Result<Vec<Box<Stmt>>, ParseError> {
+    let mut parser = parser(source)?;
+    let mut stmts = Vec::new();
+    while *parser.peek() != Token::End {
+        stmts.push(parser.stmt()?);
+    }
+    let mut ids = NodeIds::new();
+    for stmt in &mut stmts {
+        ids.visit_stmt_mut(stmt);
+    }
+    Ok(stmts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(v: i64) -> Box<Expr> {
+        Box::new(ExprKind::IntLit(v).into())
+    }
+
+    #[test]
+    fn test_parse_let_and_expr_stmts() {
+        let stmts = parse_stmts("let x = 1 + (5 - 2);\n7 - -3;").unwrap();
+        let sum = ExprKind::Add(int(1), Box::new(ExprKind::Sub(int(5), int(2)).into()));
+        assert_eq!(
+            stmts,
+            vec![
+                Box::new(StmtKind::Let(Box::new(Name::new("x")), Box::new(sum.into())).into()),
+                Box::new(StmtKind::Expr(Box::new(ExprKind::Sub(int(7), int(-3)).into())).into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_functions_blocks_and_control_flow() {
+        let source = "fn max(a, b) { if a >= b { a } else { b } }\n\
+                      let m = max(1, { let t = 2; t });";
+        let stmts = parse_stmts(source).unwrap();
+        assert_eq!(stmts.len(), 2);
+        let StmtKind::Fn(def) = &stmts[0].kind else { panic!("Expected fn definition") };
+        assert_eq!(def.name.value, "max");
+        assert_eq!(def.params.len(), 2);
+        assert!(def.body.stmts.is_empty());
+        let tail = def.body.expr.as_deref().map(|e| &e.kind);
+        assert!(matches!(tail, Some(ExprKind::If(_, _, Some(_)))));
+        let StmtKind::Let(_, call) = &stmts[1].kind else { panic!("Expected let") };
+        let ExprKind::Call(name, args) = &call.kind else { panic!("Expected call") };
+        assert_eq!(name.value, "max");
+        let ExprKind::Block(b) = &args[1].kind else { panic!("Expected block") };
+        assert!(b.stmts.len() == 1 && b.expr.is_some());
+    }
+
+    #[test]
+    fn test_boolean_precedence() {
+        let e = parse_expr("!a || b && 1 + 2 < c").unwrap();
+        let ExprKind::Or(left, right) = e.kind else { panic!("Expected ||") };
+        assert!(matches!(left.kind, ExprKind::Not(_)));
+        let ExprKind::And(_, cmp) = right.kind else { panic!("Expected &&") };
+        assert!(matches!(cmp.kind, ExprKind::Cmp(CmpOp::Lt, _, _)));
+    }
+
+    #[test]
+    fn test_nodes_carry_source_spans() {
+        let source = "let total = a + -2;\nfn f(x) { x }";
+        let stmts = parse_stmts(source).unwrap();
+        let text = |span: Option<Span>| {
+            let span = span.unwrap();
+            &source[span.start..span.end]
+        };
+        assert_eq!(text(stmts[0].span), "let total = a + -2;");
+        let StmtKind::Let(name, value) = &stmts[0].kind else { panic!("Expected let") };
+        assert_eq!(text(name.span), "total");
+        assert_eq!(text(value.span), "a + -2");
+        let ExprKind::Add(_, right) = &value.kind else { panic!("Expected +") };
+        assert_eq!(text(right.span), "-2");
+        let fn_span = stmts[1].span.unwrap();
+        assert_eq!((fn_span.line, fn_span.column), (2, 1));
+        let StmtKind::Fn(def) = &stmts[1].kind else { panic!("Expected fn") };
+        assert_eq!(text(def.body.span), "{ x }");
+        assert_ne!(stmts[0].id, stmts[1].id);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse_stmts("let x = 1;\nlet = 2;").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 5));
+        let err = parse_stmts("1 + 2").unwrap_err();
+        let expected = ParseErrorKind::Expected { expected: "';'", found: "end of input".into() };
+        assert_eq!(err.kind, expected);
+        let err = parse_expr("-(1)").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 2));
+        let err = parse_stmts("fn f(x) { let if = 1; }").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 15));
+        let err = parse_expr("{ 1 2 }").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 5));
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let levels = 100_000;
+        let deep = [
+            format!("{}1{}", "(".repeat(levels), ")".repeat(levels)),
+            format!("{}1{}", "{".repeat(levels), "}".repeat(levels)),
+            format!("{}1{}", "f(".repeat(levels), ")".repeat(levels)),
+            format!("{}true", "!".repeat(levels)),
+            vec!["1"; levels].join(" + "),
+            vec!["a"; levels].join(" && "),
+            format!("if a {{ 1 }}{} else {{ 2 }}", " else if a { 1 }".repeat(levels)),
+        ];
+        for source in &deep {
+            assert_eq!(parse_expr(source).unwrap_err().kind, ParseErrorKind::TooDeep);
+        }
+        let fns = format!("{}{}", "fn f() { ".repeat(levels), "}".repeat(levels));
+        assert_eq!(parse_stmts(&fns).unwrap_err().kind, ParseErrorKind::TooDeep);
+
+        let parens = format!("{}1{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert_eq!(parse_expr(&parens).unwrap().kind, ExprKind::IntLit(1));
+        let sum = vec!["1"; MAX_DEPTH + 1].join(" + ");
+        assert_eq!(parse_expr(&sum).unwrap().to_string(), sum);
+        let blocks = format!("{}1{}", "{ ".repeat(MAX_DEPTH), " }".repeat(MAX_DEPTH));
+        assert!(parse_expr(&blocks).unwrap().to_string().contains('1'));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashSet;
+
+use super::ast::{Expr, ExprKind, NodeId, Stmt};
+use super::{super_fold_expr, Folder};
+
+enum Folded {
+    Kind(ExprKind),
+    Overflow,
+    Unchanged,
+}
+
+impl Folded {
+    fn int(value: Option<i64>) -> Self {
+        value.map_or(Folded::Overflow, |v| Folded::Kind(ExprKind::IntLit(v)))
+    }
+}
+
+#[derive(Default)]
+pub struct ConstFolder {
+    pub overflows: usize,
+    // A PassManager revisits the same unfoldable node on every iteration.
+    overflowed: HashSet<NodeId>,
+}
+
+impl Folder for ConstFolder {
+    fn fold_expr(&mut self, e: Box<Expr>) -> Box<Expr> {
+        use ExprKind::{BoolLit, IntLit};
+        let e = super_fold_expr(self, e);
+        let folded = match &e.kind {
+            ExprKind::Add(left, right) => match (&left.kind, &right.kind) {
+                (IntLit(a), IntLit(b)) => Folded::int(a.checked_add(*b)),
+                _ => Folded::Unchanged,
+            },
+            ExprKind::Sub(left, right) => match (&left.kind, &right.kind) {
+                (IntLit(a), IntLit(b)) => Folded::int(a.checked_sub(*b)),
+                _ => Folded::Unchanged,
+            },
+            ExprKind::Cmp(op, left, right) => match (&left.kind, &right.kind) {
+                (IntLit(a), IntLit(b)) => Folded::Kind(BoolLit(op.apply(*a, *b))),
+                _ => Folded::Unchanged,
+            },
+            ExprKind::And(left, right) => match (&left.kind, &right.kind) {
+                (BoolLit(a), BoolLit(b)) => Folded::Kind(BoolLit(*a && *b)),
+                _ => Folded::Unchanged,
+            },
+            ExprKind::Or(left, right) => match (&left.kind, &right.kind) {
+                (BoolLit(a), BoolLit(b)) => Folded::Kind(BoolLit(*a || *b)),
+                _ => Folded::Unchanged,
+            },
+            ExprKind::Not(inner) => match inner.kind {
+                BoolLit(b) => Folded::Kind(BoolLit(!b)),
+                _ => Folded::Unchanged,
+            },
+            _ => Folded::Unchanged,
+        };
+        match folded {
+            Folded::Kind(kind) => Box::new(Expr { kind, ..*e }),
+            Folded::Overflow => {
+                if e.id == NodeId::DUMMY || self.overflowed.insert(e.id) {
+                    self.overflows += 1;
+                }
+                e
+            }
+            Folded::Unchanged => e,
+        }
+    }
+}
+
+// The identities only hold for ints: `true + 0` and `missing - missing` are errors that must
+// survive simplification. Without a set of nodes known to be ints (the checker's output, say)
+// only literals qualify. Hand-built nodes all share NodeId::DUMMY, so that id never vouches for
+// anything.
+#[derive(Default)]
+pub struct Simplifier {
+    ints: HashSet<NodeId>,
+}
+
+impl Simplifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_ints(ints: impl IntoIterator<Item = NodeId>) -> Self {
+        Simplifier { ints: ints.into_iter().filter(|id| *id != NodeId::DUMMY).collect() }
+    }
+
+    fn is_int(&self, e: &Expr) -> bool {
+        matches!(e.kind, ExprKind::IntLit(_)) || self.ints.contains(&e.id)
+    }
+
+    // `x - x` drops both evaluations of x, so x must also be unable to fail or overflow.
+    fn is_int_value(&self, e: &Expr) -> bool {
+        matches!(e.kind, ExprKind::IntLit(_) | ExprKind::Var(_)) && self.is_int(e)
+    }
+}
+
+impl Folder for Simplifier {
+    fn fold_expr(&mut self, e: Box<Expr>) -> Box<Expr> {
+        let Expr { id, span, kind } = *super_fold_expr(self, e);
+        let zero = ExprKind::IntLit(0);
+        let kind = match kind {
+            ExprKind::Add(left, right) if right.kind == zero && self.is_int(&left) => return left,
+            ExprKind::Add(left, right) if left.kind == zero && self.is_int(&right) => return right,
+            ExprKind::Sub(left, right) if right.kind == zero && self.is_int(&left) => return left,
+            ExprKind::Sub(left, right) if left == right && self.is_int_value(&left) => zero,
+            other => other,
+        };
+        Box::new(Expr { id, span, kind })
+    }
+}
+
+pub struct PassManager {
+    passes: Vec<Box<dyn Folder>>,
+    max_iterations: usize,
+    iterations: usize,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        PassManager { passes: Vec::new(), max_iterations: 32, iterations: 0 }
+    }
+
+    pub fn with_pass(mut self, pass: impl Folder + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    pub fn run_expr(&mut self, e: Box<Expr>) -> Box<Expr> {
+        self.fixpoint(e, |pass, e| pass.fold_expr(e))
+    }
+
+    pub fn run_stmt(&mut self, s: Box<Stmt>) -> Box<Stmt> {
+        self.fixpoint(s, |pass, s| pass.fold_stmt(s))
+    }
+
+    fn fixpoint<T: Clone + PartialEq>(
+        &mut self,
+        mut node: Box<T>,
+        run: impl Fn(&mut dyn Folder, Box<T>) -> Box<T>,
+    ) -> Box<T> {
+        self.iterations = 0;
+        while self.iterations < self.max_iterations {
+            self.iterations += 1;
+            let before = node.clone();
+            for pass in &mut self.passes {
+                node = run(pass.as_mut(), node);
+            }
+            if node == before {
+                break;
+            }
+        }
+        node
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager::new().with_pass(ConstFolder::default()).with_pass(Simplifier::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ast::Name;
+    use super::super::check::{Checker, Ty};
+    use super::super::parser::{parse_expr, parse_stmts};
+    use super::*;
+
+    fn run(pass: &mut impl Folder, source: &str) -> String {
+        pass.fold_expr(parse_expr(source).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_const_folder() {
+        assert_eq!(run(&mut ConstFolder::default(), "1 + (5 - 2) - -4"), "8");
+        let mut folder = ConstFolder::default();
+        assert_eq!(run(&mut folder, "9223372036854775807 + 1 + 2"), "9223372036854775807 + 1 + 2");
+        assert_eq!(folder.overflows, 1);
+    }
+
+    #[test]
+    fn test_const_folder_comparisons_and_booleans() {
+        let mut folder = ConstFolder::default();
+        assert_eq!(run(&mut folder, "1 + 2 < 4 && !(3 == 4 - 1) || false"), "false");
+        assert_eq!(run(&mut folder, "x < 1 + 1"), "x < 2");
+    }
+
+    #[test]
+    fn test_const_folder_counts_each_overflow_once() {
+        let mut folder = ConstFolder::default();
+        let source = "(9223372036854775807 + 1) - (0 - 9223372036854775807 - 2)";
+        let mut e = parse_expr(source).unwrap();
+        for _ in 0..3 {
+            e = folder.fold_expr(e);
+        }
+        assert_eq!(folder.overflows, 2);
+    }
+
+    #[test]
+    fn test_simplifier_identities() {
+        assert_eq!(run(&mut Simplifier::new(), "0 + (7 - 0) + 0"), "7");
+        assert_eq!(run(&mut Simplifier::new(), "3 - 3"), "0");
+        assert_eq!(run(&mut Simplifier::new(), "1 - 2"), "1 - 2");
+    }
+
+    #[test]
+    fn test_simplifier_keeps_errors_it_cannot_rule_out() {
+        for source in ["true - true", "true + 0", "0 + false", "missing - missing", "x - 0"] {
+            assert_eq!(run(&mut Simplifier::new(), source), source);
+        }
+        let overflow = "9223372036854775807 + 1 - (9223372036854775807 + 1)";
+        assert_eq!(run(&mut Simplifier::new(), overflow), overflow);
+        let mut manager = PassManager::default();
+        assert_eq!(manager.run_expr(parse_expr(overflow).unwrap()).to_string(), overflow);
+    }
+
+    #[test]
+    fn test_simplifier_uses_checked_types() {
+        let source = "let n = 5; let b = true;\n\
+                      n - n; n + 0; b - b; (n + n) - (n + n); (n + n) + 0;";
+        let mut checker = Checker::new();
+        let stmts = checker.check(parse_stmts(source).unwrap());
+        assert_eq!(checker.diagnostics.len(), 2);
+        let ints = checker.types.iter().filter(|(_, ty)| **ty == Ty::Int).map(|(id, _)| id);
+        let mut simplifier = Simplifier::with_ints(ints);
+        let simplified: Vec<_> =
+            stmts.into_iter().map(|s| simplifier.fold_stmt(s).to_string()).collect();
+        assert_eq!(
+            simplified[2..],
+            ["0;", "n;", "b - b;", "n + n - (n + n);", "n + n;"]
+        );
+    }
+
+    #[test]
+    fn test_simplifier_ignores_dummy_ids() {
+        let var = |name: &str| Box::new(Expr::new(ExprKind::Var(Box::new(Name::new(name)))));
+        let e = Box::new(Expr::new(ExprKind::Sub(var("b"), var("b"))));
+        let mut simplifier = Simplifier::with_ints([NodeId::DUMMY]);
+        assert_eq!(simplifier.fold_expr(e).to_string(), "b - b");
+    }
+
+    #[test]
+    fn test_pass_manager_reaches_fixpoint() {
+        let mut manager = PassManager::new()
+            .with_pass(Simplifier::new())
+            .with_pass(ConstFolder::default());
+        let stmt = parse_stmts("let x = (3 - (1 + 2)) + ((4 - 4) - 0) + 5;").unwrap().remove(0);
+        assert_eq!(manager.run_stmt(stmt).to_string(), "let x = 5;");
+        assert!(manager.iterations() >= 2);
+
+        let mut manager = PassManager::default().with_max_iterations(1);
+        let e = manager.run_expr(parse_expr("1 + 1").unwrap());
+        assert_eq!(e.kind, ExprKind::IntLit(2));
+        assert_eq!(manager.iterations(), 1);
+    }
+
+    #[test]
+    fn test_folding_keeps_ids_and_spans() {
+        let e = parse_expr("x + (1 + 2)").unwrap();
+        let ExprKind::Add(left, right) = &e.kind else { panic!("Expected +") };
+        let (outer, var, inner) = ((e.id, e.span), (left.id, left.span), (right.id, right.span));
+        let folded = ConstFolder::default().fold_expr(e);
+        assert_eq!((folded.id, folded.span), outer);
+        let ExprKind::Add(left, right) = &folded.kind else { panic!("Expected +") };
+        assert_eq!((left.id, left.span), var);
+        assert_eq!(right.kind, ExprKind::IntLit(3));
+        assert_eq!((right.id, right.span), inner);
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+
+use super::ast::{Block, Expr, ExprKind, Stmt, StmtKind};
+
+const OR: u8 = 1;
+const AND: u8 = 2;
+const CMP: u8 = 3;
+const ADDITIVE: u8 = 4;
+const UNARY: u8 = 5;
+const ATOM: u8 = 6;
+
+fn precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Or(..) => OR,
+        ExprKind::And(..) => AND,
+        ExprKind::Cmp(..) => CMP,
+        ExprKind::Add(..) | ExprKind::Sub(..) => ADDITIVE,
+        ExprKind::Not(_) => UNARY,
+        ExprKind::IntLit(v) if *v < 0 => UNARY,
+        _ => ATOM,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Printer {
+    indent: String,
+    level: usize,
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Printer { indent: "    ".to_string(), level: 0 }
+    }
+
+    pub fn with_indent(mut self, indent: impl Into<String>) -> Self {
+        self.indent = indent.into();
+        self
+    }
+
+    pub fn at_level(mut self, level: usize) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn print_expr(&self, expr: &Expr) -> String {
+        let mut out = String::new();
+        self.write_expr(&mut out, expr, OR, self.level);
+        out
+    }
+
+    pub fn print_stmt(&self, stmt: &Stmt) -> String {
+        let mut out = String::new();
+        self.write_stmt(&mut out, stmt, self.level);
+        out
+    }
+
+    pub fn print_stmts<'a>(&self, stmts: impl IntoIterator<Item = &'a Stmt>) -> String {
+        let mut out = String::new();
+        for stmt in stmts {
+            self.write_stmt(&mut out, stmt, self.level);
+            out.push('\n');
+        }
+        out
+    }
+
+    fn write_stmt(&self, out: &mut String, stmt: &Stmt, level: usize) {
+        out.push_str(&self.indent.repeat(level));
+        match &stmt.kind {
+            StmtKind::Expr(e) => {
+                self.write_expr(out, e, OR, level);
+                out.push(';');
+            }
+            StmtKind::Let(n, e) => {
+                out.push_str("let ");
+                out.push_str(&n.value);
+                out.push_str(" = ");
+                self.write_expr(out, e, OR, level);
+                out.push(';');
+            }
+            StmtKind::Fn(d) => {
+                out.push_str("fn ");
+                out.push_str(&d.name.value);
+                out.push('(');
+                let params: Vec<&str> = d.params.iter().map(|p| p.value.as_str()).collect();
+                out.push_str(&params.join(", "));
+                out.push_str(") ");
+                self.write_block(out, &d.body, level);
+            }
+        }
+    }
+
+    fn write_block(&self, out: &mut String, block: &Block, level: usize) {
+        match (&block.stmts[..], &block.expr) {
+            ([], None) => out.push_str("{}"),
+            ([], Some(e)) => {
+                out.push_str("{ ");
+                self.write_expr(out, e, OR, level + 1);
+                out.push_str(" }");
+            }
+            (stmts, tail) => {
+                out.push_str("{\n");
+                for stmt in stmts {
+                    self.write_stmt(out, stmt, level + 1);
+                    out.push('\n');
+                }
+                if let Some(e) = tail {
+                    out.push_str(&self.indent.repeat(level + 1));
+                    self.write_expr(out, e, OR, level + 1);
+                    out.push('\n');
+                }
+                out.push_str(&self.indent.repeat(level));
+                out.push('}');
+            }
+        }
+    }
+
+    fn write_expr(&self, out: &mut String, expr: &Expr, min: u8, level: usize) {
+        let parens = precedence(expr) < min;
+        if parens {
+            out.push('(');
+        }
+        match &expr.kind {
+            ExprKind::IntLit(v) => out.push_str(&v.to_string()),
+            ExprKind::BoolLit(b) => out.push_str(if *b { "true" } else { "false" }),
+            ExprKind::Var(n) => out.push_str(&n.value),
+            ExprKind::Add(left, right) => {
+                self.write_infix(out, left, " + ", right, (ADDITIVE, UNARY), level);
+            }
+            ExprKind::Sub(left, right) => {
+                self.write_infix(out, left, " - ", right, (ADDITIVE, UNARY), level);
+            }
+            ExprKind::Cmp(op, left, right) => {
+                let symbol = format!(" {} ", op.symbol());
+                self.write_infix(out, left, &symbol, right, (ADDITIVE, ADDITIVE), level);
+            }
+            ExprKind::And(left, right) => {
+                self.write_infix(out, left, " && ", right, (AND, CMP), level);
+            }
+            ExprKind::Or(left, right) => {
+                self.write_infix(out, left, " || ", right, (OR, AND), level);
+            }
+            ExprKind::Not(inner) => {
+                out.push('!');
+                self.write_expr(out, inner, UNARY, level);
+            }
+            ExprKind::Block(b) => self.write_block(out, b, level),
+            ExprKind::If(cond, then, els) => {
+                out.push_str("if ");
+                self.write_expr(out, cond, OR, level);
+                out.push(' ');
+                self.write_block(out, then, level);
+                match els.as_deref() {
+                    Some(Block { stmts, expr: Some(nested), .. })
+                        if stmts.is_empty() && matches!(nested.kind, ExprKind::If(..)) =>
+                    {
+                        out.push_str(" else ");
+                        self.write_expr(out, nested, OR, level);
+                    }
+                    Some(block) => {
+                        out.push_str(" else ");
+                        self.write_block(out, block, level);
+                    }
+                    None => {}
+                }
+            }
+            ExprKind::Call(n, args) => {
+                out.push_str(&n.value);
+                out.push('(');
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.write_expr(out, arg, OR, level);
+                }
+                out.push(')');
+            }
+        }
+        if parens {
+            out.push(')');
+        }
+    }
+
+    fn write_infix(
+        &self,
+        out: &mut String,
+        left: &Expr,
+        symbol: &str,
+        right: &Expr,
+        (left_min, right_min): (u8, u8),
+        level: usize,
+    ) {
+        self.write_expr(out, left, left_min, level);
+        out.push_str(symbol);
+        self.write_expr(out, right, right_min, level);
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::new().print_expr(self))
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::new().print_stmt(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::{parse_expr, parse_stmts};
+    use super::*;
+
+    #[test]
+    fn test_parentheses_only_where_needed() {
+        let expr = parse_expr("((1 + 2) - (3 - (4 + -5)))").unwrap();
+        assert_eq!(expr.to_string(), "1 + 2 - (3 - (4 + -5))");
+    }
+
+    #[test]
+    fn test_indentation() {
+        let stmts = parse_stmts("let x = 1 + 2; 7 - 1;").unwrap();
+        let printer = Printer::new().with_indent("\t").at_level(2);
+        assert_eq!(
+            printer.print_stmts(stmts.iter().map(|s| s.as_ref())),
+            "\t\tlet x = 1 + 2;\n\t\t7 - 1;\n"
+        );
+    }
+
+    #[test]
+    fn test_precedence_of_new_operators() {
+        let expr = parse_expr("(a || b) && !(c < d) || (1 < 2) == (3 < 4)").unwrap();
+        assert_eq!(expr.to_string(), "(a || b) && !(c < d) || (1 < 2) == (3 < 4)");
+        let expr = parse_expr("((a && b) && c) || (x - (y + 1) >= -2)").unwrap();
+        assert_eq!(expr.to_string(), "a && b && c || x - (y + 1) >= -2");
+    }
+
+    #[test]
+    fn test_nested_block_indentation() {
+        let source = concat!(
+            "fn f(n) {\n",
+            "  let m = n - 1;\n",
+            "  if n <= 0 { 0 } else if n == 1 {\n",
+            "    f(m);\n",
+            "    1\n",
+            "  } else { f(m) + n }\n",
+            "}\n",
+        );
+        let stmts = parse_stmts(source).unwrap();
+        let printer = Printer::new().with_indent("  ");
+        let printed = printer.print_stmts(stmts.iter().map(|s| s.as_ref()));
+        assert_eq!(printed, source);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = concat!(
+            "let a = 1 - (2 - 3) + 4;\n",
+            "-9223372036854775808 - -1;\n",
+            "0;\n",
+            "fn g() {}\n",
+            "let b = {\n",
+            "    let c = g();\n",
+            "    !c\n",
+            "};\n",
+        );
+        let stmts = parse_stmts(source).unwrap();
+        let printed = Printer::new().print_stmts(stmts.iter().map(|s| s.as_ref()));
+        assert_eq!(printed, source);
+        assert_eq!(parse_stmts(&printed).unwrap(), stmts);
+    }
+}
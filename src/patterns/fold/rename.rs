@@ -0,0 +1,306 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use super::ast::{Block, Expr, ExprKind, FnDef, Name, Stmt, StmtKind};
+use super::{super_fold_block, super_fold_expr, Folder, Visit};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameIssue {
+    Collision { from: String, to: String },
+    Shadowing { name: String },
+}
+
+#[derive(Default)]
+struct Scopes {
+    frames: Vec<HashMap<String, String>>,
+}
+
+impl Scopes {
+    fn push(&mut self) {
+        self.frames.push(HashMap::new());
+    }
+
+    fn pop(&mut self) {
+        self.frames.pop();
+    }
+
+    fn lookup(&self, name: &str) -> Option<&String> {
+        self.frames.iter().rev().find_map(|frame| frame.get(name))
+    }
+
+    fn bind(&mut self, name: String, renamed: String) {
+        if self.frames.is_empty() {
+            self.push();
+        }
+        self.frames.last_mut().unwrap().insert(name, renamed);
+    }
+
+    fn holder_of(&self, renamed: &str, except: &str) -> Option<&String> {
+        self.frames
+            .iter()
+            .flat_map(|frame| frame.iter())
+            .find(|(from, to)| to.as_str() == renamed && from.as_str() != except)
+            .map(|(from, _)| from)
+    }
+}
+
+pub struct ScopedRenamer {
+    mapping: HashMap<String, String>,
+    fresh: Option<NameGen>,
+    scopes: Scopes,
+    pub issues: Vec<RenameIssue>,
+}
+
+impl ScopedRenamer {
+    pub fn new<K, V>(mapping: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        ScopedRenamer {
+            mapping: mapping.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+            fresh: None,
+            scopes: Scopes::default(),
+            issues: Vec::new(),
+        }
+    }
+
+    pub fn rename_all(&mut self, stmts: Vec<Box<Stmt>>) -> Vec<Box<Stmt>> {
+        stmts.into_iter().map(|s| self.fold_stmt(s)).collect()
+    }
+
+    fn bind(&mut self, mut n: Box<Name>) -> Box<Name> {
+        let name = n.value.as_str();
+        let renamed = match &mut self.fresh {
+            Some(names) => names.fresh(name),
+            None => {
+                let renamed = self.mapping.get(name).cloned().unwrap_or_else(|| name.to_string());
+                if self.scopes.lookup(name).is_some() {
+                    self.issues.push(RenameIssue::Shadowing { name: name.to_string() });
+                }
+                if self.scopes.holder_of(&renamed, name).is_some() {
+                    let from = name.to_string();
+                    self.issues.push(RenameIssue::Collision { from, to: renamed.clone() });
+                }
+                renamed
+            }
+        };
+        self.scopes.bind(name.to_string(), renamed.clone());
+        n.value = renamed;
+        n
+    }
+
+    fn resolve(&mut self, mut n: Box<Name>) -> Box<Name> {
+        if let Some(renamed) = self.scopes.lookup(&n.value) {
+            n.value = renamed.clone();
+            return n;
+        }
+        if let Some(holder) = self.scopes.holder_of(&n.value, &n.value) {
+            let from = holder.clone();
+            self.issues.push(RenameIssue::Collision { from, to: n.value.clone() });
+        }
+        n
+    }
+}
+
+impl Folder for ScopedRenamer {
+    fn fold_expr(&mut self, e: Box<Expr>) -> Box<Expr> {
+        let Expr { id, span, kind } = *e;
+        let kind = match kind {
+            ExprKind::Var(n) => ExprKind::Var(self.resolve(n)),
+            ExprKind::Call(n, args) => {
+                let n = self.resolve(n);
+                ExprKind::Call(n, args.into_iter().map(|a| self.fold_expr(a)).collect())
+            }
+            other => return super_fold_expr(self, Box::new(Expr { id, span, kind: other })),
+        };
+        Box::new(Expr { id, span, kind })
+    }
+
+    fn fold_stmt(&mut self, s: Box<Stmt>) -> Box<Stmt> {
+        let Stmt { id, span, kind } = *s;
+        let kind = match kind {
+            StmtKind::Expr(e) => StmtKind::Expr(self.fold_expr(e)),
+            StmtKind::Let(n, e) => {
+                let e = self.fold_expr(e);
+                StmtKind::Let(self.bind(n), e)
+            }
+            StmtKind::Fn(d) => StmtKind::Fn(self.fold_fn_def(d)),
+        };
+        Box::new(Stmt { id, span, kind })
+    }
+
+    fn fold_block(&mut self, b: Box<Block>) -> Box<Block> {
+        self.scopes.push();
+        let b = super_fold_block(self, b);
+        self.scopes.pop();
+        b
+    }
+
+    fn fold_fn_def(&mut self, d: Box<FnDef>) -> Box<FnDef> {
+        let FnDef { id, span, name, params, body } = *d;
+        let name = self.bind(name);
+        self.scopes.push();
+        let params = params.into_iter().map(|p| self.bind(p)).collect();
+        let body = self.fold_block(body);
+        self.scopes.pop();
+        Box::new(FnDef { id, span, name, params, body })
+    }
+}
+
+#[derive(Default)]
+struct NameCollector(HashSet<String>);
+
+impl Visit for NameCollector {
+    fn visit_name(&mut self, n: &Name) {
+        self.0.insert(n.value.clone());
+    }
+}
+
+pub struct NameGen {
+    used: HashSet<String>,
+}
+
+impl NameGen {
+    pub fn new() -> Self {
+        NameGen { used: HashSet::new() }
+    }
+
+    pub fn avoiding<'a>(stmts: impl IntoIterator<Item = &'a Stmt>) -> Self {
+        let mut collector = NameCollector::default();
+        for stmt in stmts {
+            collector.visit_stmt(stmt);
+        }
+        NameGen { used: collector.0 }
+    }
+
+    pub fn reserve(&mut self, name: impl Into<String>) {
+        self.used.insert(name.into());
+    }
+
+    pub fn fresh(&mut self, base: &str) -> String {
+        let base = base.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+        let base = if base.is_empty() { "v" } else { base };
+        let name = (1..)
+            .map(|i| format!("{}_{}", base, i))
+            .find(|candidate| !self.used.contains(candidate))
+            .unwrap();
+        self.used.insert(name.clone());
+        name
+    }
+}
+
+impl Default for NameGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Fresh names must not capture free variables, so every name already in the tree is reserved
+// whether or not `names` was built with `NameGen::avoiding`.
+pub fn alpha_rename(stmts: Vec<Box<Stmt>>, names: &mut NameGen) -> Vec<Box<Stmt>> {
+    let mut collector = NameCollector::default();
+    for stmt in &stmts {
+        collector.visit_stmt(stmt);
+    }
+    names.used.extend(collector.0);
+    let mut renamer = ScopedRenamer::new(Vec::<(String, String)>::new());
+    renamer.fresh = Some(mem::take(names));
+    let renamed = renamer.rename_all(stmts);
+    *names = renamer.fresh.take().unwrap_or_default();
+    renamed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::parse_stmts;
+    use super::*;
+
+    fn print(stmts: &[Box<Stmt>]) -> Vec<String> {
+        stmts.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_renames_only_mapped_bindings() {
+        let stmts = parse_stmts("let x = 1; let y = 2;").unwrap();
+        let mut renamer = ScopedRenamer::new([("x", "total")]);
+        let renamed = renamer.rename_all(stmts);
+        assert_eq!(print(&renamed), vec!["let total = 1;", "let y = 2;"]);
+        assert!(renamer.issues.is_empty());
+    }
+
+    #[test]
+    fn test_detects_collision_and_shadowing() {
+        let stmts = parse_stmts("let y = 1; let x = 2; let x = 3;").unwrap();
+        let mut renamer = ScopedRenamer::new([("x", "y")]);
+        renamer.rename_all(stmts);
+        assert_eq!(
+            renamer.issues,
+            vec![
+                RenameIssue::Collision { from: "x".into(), to: "y".into() },
+                RenameIssue::Shadowing { name: "x".into() },
+                RenameIssue::Collision { from: "x".into(), to: "y".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_references_follow_their_binding() {
+        let source = "let x = 1; let y = { let x = 2; x + y }; x + y;";
+        let mut renamer = ScopedRenamer::new([("x", "a")]);
+        let renamed = renamer.rename_all(parse_stmts(source).unwrap());
+        assert_eq!(
+            print(&renamed),
+            vec!["let a = 1;", "let y = {\n    let a = 2;\n    a + y\n};", "a + y;"]
+        );
+        assert_eq!(renamer.issues, vec![RenameIssue::Shadowing { name: "x".into() }]);
+    }
+
+    #[test]
+    fn test_functions_and_params_are_scoped() {
+        let source = "fn f(n) { if n > 0 { f(n - 1) } else { n } } f(n);";
+        let mut renamer = ScopedRenamer::new([("n", "k"), ("f", "g")]);
+        let renamed = renamer.rename_all(parse_stmts(source).unwrap());
+        assert_eq!(
+            print(&renamed),
+            vec!["fn g(k) { if k > 0 { g(k - 1) } else { k } }", "g(n);"]
+        );
+        assert!(renamer.issues.is_empty());
+    }
+
+    #[test]
+    fn test_detects_capture_of_free_variable() {
+        let stmts = parse_stmts("let x = 1; x + y;").unwrap();
+        let mut renamer = ScopedRenamer::new([("x", "y")]);
+        renamer.rename_all(stmts);
+        let expected = RenameIssue::Collision { from: "x".into(), to: "y".into() };
+        assert_eq!(renamer.issues, vec![expected]);
+    }
+
+    #[test]
+    fn test_alpha_rename_removes_shadowing() {
+        let stmts = parse_stmts("let x = 1; let x = x + 1; fn f(x) { x } f(x);").unwrap();
+        let mut names = NameGen::avoiding(stmts.iter().map(|s| s.as_ref()));
+        let renamed = alpha_rename(stmts, &mut names);
+        assert_eq!(
+            print(&renamed),
+            vec!["let x_1 = 1;", "let x_2 = x_1 + 1;", "fn f_1(x_3) { x_3 }", "f_1(x_2);"]
+        );
+    }
+
+    #[test]
+    fn test_alpha_rename_does_not_capture_free_variables() {
+        let stmts = parse_stmts("let x = 1; x + x_1;").unwrap();
+        let renamed = alpha_rename(stmts, &mut NameGen::new());
+        assert_eq!(print(&renamed), vec!["let x_2 = 1;", "x_2 + x_1;"]);
+    }
+
+    #[test]
+    fn test_alpha_rename_generates_fresh_names() {
+        let stmts = parse_stmts("let x = 1; let x_1 = 2; let x = 3;").unwrap();
+        let mut names = NameGen::avoiding(stmts.iter().map(|s| s.as_ref()));
+        let renamed = alpha_rename(stmts, &mut names);
+        assert_eq!(print(&renamed), vec!["let x_2 = 1;", "let x_3 = 2;", "let x_4 = 3;"]);
+        assert_eq!(names.fresh("x"), "x_5");
+    }
+}
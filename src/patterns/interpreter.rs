@@ -1,5 +1,179 @@
+pub mod bytecode;
+pub mod parser;
+pub mod printer;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    UnboundVariable(String),
+    Overflow,
+    DivisionByZero,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::UnboundVariable(name) => write!(f, "unbound variable '{}'", name),
+            EvalError::Overflow => write!(f, "arithmetic overflow"),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArithmeticMode {
+    #[default]
+    Checked,
+    Wrapping,
+    Saturating,
+}
+
+impl ArithmeticMode {
+    pub fn add(self, a: i32, b: i32) -> Result<i32, EvalError> {
+        match self {
+            ArithmeticMode::Checked => a.checked_add(b).ok_or(EvalError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_add(b)),
+            ArithmeticMode::Saturating => Ok(a.saturating_add(b)),
+        }
+    }
+
+    pub fn sub(self, a: i32, b: i32) -> Result<i32, EvalError> {
+        match self {
+            ArithmeticMode::Checked => a.checked_sub(b).ok_or(EvalError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_sub(b)),
+            ArithmeticMode::Saturating => Ok(a.saturating_sub(b)),
+        }
+    }
+
+    pub fn mul(self, a: i32, b: i32) -> Result<i32, EvalError> {
+        match self {
+            ArithmeticMode::Checked => a.checked_mul(b).ok_or(EvalError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_mul(b)),
+            ArithmeticMode::Saturating => Ok(a.saturating_mul(b)),
+        }
+    }
+
+    pub fn div(self, a: i32, b: i32) -> Result<i32, EvalError> {
+        if b == 0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        match self {
+            ArithmeticMode::Checked => a.checked_div(b).ok_or(EvalError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_div(b)),
+            ArithmeticMode::Saturating => Ok(a.saturating_div(b)),
+        }
+    }
+
+    pub fn rem(self, a: i32, b: i32) -> Result<i32, EvalError> {
+        if b == 0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        match self {
+            ArithmeticMode::Checked => a.checked_rem(b).ok_or(EvalError::Overflow),
+            ArithmeticMode::Wrapping | ArithmeticMode::Saturating => Ok(a.wrapping_rem(b)),
+        }
+    }
+
+    pub fn neg(self, a: i32) -> Result<i32, EvalError> {
+        match self {
+            ArithmeticMode::Checked => a.checked_neg().ok_or(EvalError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_neg()),
+            ArithmeticMode::Saturating => Ok(a.saturating_neg()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Environment<'a> {
+    bindings: HashMap<String, i32>,
+    parent: Option<&'a Environment<'a>>,
+    mode: ArithmeticMode,
+}
+
+impl<'a> Environment<'a> {
+    pub fn new() -> Self {
+        Environment { bindings: HashMap::new(), parent: None, mode: ArithmeticMode::default() }
+    }
+
+    pub fn with_mode(mut self, mode: ArithmeticMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> ArithmeticMode {
+        self.mode
+    }
+
+    pub fn with(mut self, name: impl Into<String>, value: i32) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: i32) {
+        self.bindings.insert(name.into(), value);
+    }
+
+    pub fn child(&'a self) -> Environment<'a> {
+        Environment { bindings: HashMap::new(), parent: Some(self), mode: self.mode }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<i32> {
+        match self.bindings.get(name) {
+            Some(value) => Some(*value),
+            None => self.parent.and_then(|parent| parent.lookup(name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+pub trait ExpressionVisitor {
+    fn visit_number(&mut self, value: i32);
+    fn visit_variable(&mut self, name: &str);
+    fn visit_binary(&mut self, op: BinaryOp, left: &dyn Expression, right: &dyn Expression);
+    fn visit_negate(&mut self, operand: &dyn Expression);
+    fn visit_let(&mut self, name: &str, value: &dyn Expression, body: &dyn Expression);
+    fn visit_opaque(&mut self);
+}
+
 pub trait Expression {
-    fn interpret(&self) -> i32;
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError>;
+
+    // Expressions that don't describe their structure still evaluate; the bytecode compiler
+    // rejects them as opaque and the printer shows a placeholder.
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_opaque();
+    }
+
+    fn try_interpret(&self) -> Result<i32, EvalError> {
+        self.evaluate(&Environment::new())
+    }
+
+    // Panics where `try_interpret` would return an error.
+    fn interpret(&self) -> i32 {
+        match self.try_interpret() {
+            Ok(value) => value,
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+impl fmt::Display for dyn Expression + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&printer::to_source(self))
+    }
 }
 
 pub struct Number(i32);
@@ -11,8 +185,12 @@ impl Number {
 }
 
 impl Expression for Number {
-    fn interpret(&self) -> i32 {
-        self.0
+    fn evaluate(&self, _env: &Environment) -> Result<i32, EvalError> {
+        Ok(self.0)
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_number(self.0);
     }
 }
 
@@ -28,8 +206,12 @@ impl Plus {
 }
 
 impl Expression for Plus {
-    fn interpret(&self) -> i32 {
-        self.left.interpret() + self.right.interpret()
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError> {
+        env.mode().add(self.left.evaluate(env)?, self.right.evaluate(env)?)
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_binary(BinaryOp::Add, self.left.as_ref(), self.right.as_ref());
     }
 }
 
@@ -45,8 +227,141 @@ impl Minus {
 }
 
 impl Expression for Minus {
-    fn interpret(&self) -> i32 {
-        self.left.interpret() - self.right.interpret()
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError> {
+        env.mode().sub(self.left.evaluate(env)?, self.right.evaluate(env)?)
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_binary(BinaryOp::Sub, self.left.as_ref(), self.right.as_ref());
+    }
+}
+
+pub struct Times {
+    left: Box<dyn Expression>,
+    right: Box<dyn Expression>,
+}
+
+impl Times {
+    pub fn new(left: Box<dyn Expression>, right: Box<dyn Expression>) -> Self {
+        Times { left, right }
+    }
+}
+
+impl Expression for Times {
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError> {
+        env.mode().mul(self.left.evaluate(env)?, self.right.evaluate(env)?)
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_binary(BinaryOp::Mul, self.left.as_ref(), self.right.as_ref());
+    }
+}
+
+pub struct Divide {
+    left: Box<dyn Expression>,
+    right: Box<dyn Expression>,
+}
+
+impl Divide {
+    pub fn new(left: Box<dyn Expression>, right: Box<dyn Expression>) -> Self {
+        Divide { left, right }
+    }
+}
+
+impl Expression for Divide {
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError> {
+        env.mode().div(self.left.evaluate(env)?, self.right.evaluate(env)?)
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_binary(BinaryOp::Div, self.left.as_ref(), self.right.as_ref());
+    }
+}
+
+pub struct Modulo {
+    left: Box<dyn Expression>,
+    right: Box<dyn Expression>,
+}
+
+impl Modulo {
+    pub fn new(left: Box<dyn Expression>, right: Box<dyn Expression>) -> Self {
+        Modulo { left, right }
+    }
+}
+
+impl Expression for Modulo {
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError> {
+        env.mode().rem(self.left.evaluate(env)?, self.right.evaluate(env)?)
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_binary(BinaryOp::Rem, self.left.as_ref(), self.right.as_ref());
+    }
+}
+
+pub struct Negate {
+    operand: Box<dyn Expression>,
+}
+
+impl Negate {
+    pub fn new(operand: Box<dyn Expression>) -> Self {
+        Negate { operand }
+    }
+}
+
+impl Expression for Negate {
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError> {
+        env.mode().neg(self.operand.evaluate(env)?)
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_negate(self.operand.as_ref());
+    }
+}
+
+pub struct Variable(String);
+
+impl Variable {
+    pub fn new(name: impl Into<String>) -> Self {
+        Variable(name.into())
+    }
+}
+
+impl Expression for Variable {
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError> {
+        env.lookup(&self.0).ok_or_else(|| EvalError::UnboundVariable(self.0.clone()))
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_variable(&self.0);
+    }
+}
+
+pub struct Let {
+    name: String,
+    value: Box<dyn Expression>,
+    body: Box<dyn Expression>,
+}
+
+impl Let {
+    pub fn new(
+        name: impl Into<String>,
+        value: Box<dyn Expression>,
+        body: Box<dyn Expression>,
+    ) -> Self {
+        Let { name: name.into(), value, body }
+    }
+}
+
+impl Expression for Let {
+    fn evaluate(&self, env: &Environment) -> Result<i32, EvalError> {
+        let value = self.value.evaluate(env)?;
+        let scope = env.child().with(self.name.as_str(), value);
+        self.body.evaluate(&scope)
+    }
+
+    fn accept(&self, visitor: &mut dyn ExpressionVisitor) {
+        visitor.visit_let(&self.name, self.value.as_ref(), self.body.as_ref());
     }
 }
 
@@ -58,7 +373,51 @@ pub fn demo() {
         )),
         Box::new(Number::new(3)),
     );
-    println!("[Interpreter demo] result = {}", expr.interpret());
+    match expr.evaluate(&Environment::new()) {
+        Ok(value) => println!("[Interpreter demo] result = {}", value),
+        Err(e) => println!("[Interpreter demo] error: {}", e),
+    }
+
+    let source = "10 - (4 + -3)";
+    match parser::parse(source) {
+        Ok(parsed) => match parsed.evaluate(&Environment::new()) {
+            Ok(value) => println!("[Interpreter demo] {} = {}", parsed, value),
+            Err(e) => println!("[Interpreter demo] {} failed: {}", parsed, e),
+        },
+        Err(e) => println!("[Interpreter demo] parse error: {}", e),
+    }
+
+    let source = "2147483647 + 1";
+    if let Ok(parsed) = parser::parse(source) {
+        let modes = [ArithmeticMode::Checked, ArithmeticMode::Wrapping, ArithmeticMode::Saturating];
+        for mode in modes {
+            let env = Environment::new().with_mode(mode);
+            println!("[Interpreter demo] {:?}: {} = {:?}", mode, source, parsed.evaluate(&env));
+        }
+    }
+
+    let env = Environment::new().with("rate", 7);
+    let source = "let base = 10 in base + rate - x";
+    match parser::parse(source) {
+        Ok(parsed) => match parsed.evaluate(&env) {
+            Ok(value) => println!("[Interpreter demo] {} = {}", source, value),
+            Err(e) => println!("[Interpreter demo] {} failed: {}", source, e),
+        },
+        Err(e) => println!("[Interpreter demo] parse error: {}", e),
+    }
+
+    if let Ok(parsed) = parser::parse("x * x - 3 * x + 2") {
+        match bytecode::compile(parsed.as_ref()) {
+            Ok(program) => {
+                let mut vm = bytecode::Vm::new();
+                let results: Vec<_> = (0..5)
+                    .map(|x| vm.run(&program, &Environment::new().with("x", x)))
+                    .collect();
+                println!("[Interpreter demo] bytecode {:?} -> {:?}", program.code(), results);
+            }
+            Err(e) => println!("[Interpreter demo] compile error: {}", e),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -87,6 +446,93 @@ mod tests {
         assert_eq!(minus.interpret(), 2);
     }
 
+    #[test]
+    fn test_negate() {
+        let negate = Negate::new(Box::new(Number::new(7)));
+        assert_eq!(negate.interpret(), -7);
+    }
+
+    #[test]
+    fn test_variables_from_environment() {
+        let env = Environment::new().with("x", 4);
+        let expr = Plus::new(Box::new(Variable::new("x")), Box::new(Number::new(1)));
+        assert_eq!(expr.evaluate(&env), Ok(5));
+        assert_eq!(
+            expr.evaluate(&Environment::new()),
+            Err(EvalError::UnboundVariable("x".into()))
+        );
+    }
+
+    #[test]
+    fn test_let_scoping() {
+        let env = Environment::new().with("x", 1);
+        let inner = Let::new("x", Box::new(Number::new(10)), Box::new(Variable::new("x")));
+        let expr = Plus::new(Box::new(inner), Box::new(Variable::new("x")));
+        assert_eq!(expr.evaluate(&env), Ok(11));
+
+        let leaked = Plus::new(
+            Box::new(Let::new("y", Box::new(Number::new(2)), Box::new(Variable::new("y")))),
+            Box::new(Variable::new("y")),
+        );
+        assert_eq!(leaked.evaluate(&env), Err(EvalError::UnboundVariable("y".into())));
+    }
+
+    #[test]
+    fn test_times_divide_modulo() {
+        let env = Environment::new();
+        let times = Times::new(Box::new(Number::new(6)), Box::new(Number::new(7)));
+        assert_eq!(times.evaluate(&env), Ok(42));
+        let divide = Divide::new(Box::new(Number::new(-7)), Box::new(Number::new(2)));
+        assert_eq!(divide.evaluate(&env), Ok(-3));
+        let modulo = Modulo::new(Box::new(Number::new(-7)), Box::new(Number::new(2)));
+        assert_eq!(modulo.evaluate(&env), Ok(-1));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let modes = [ArithmeticMode::Checked, ArithmeticMode::Wrapping, ArithmeticMode::Saturating];
+        for mode in modes {
+            let env = Environment::new().with_mode(mode);
+            let divide = Divide::new(Box::new(Number::new(1)), Box::new(Number::new(0)));
+            assert_eq!(divide.evaluate(&env), Err(EvalError::DivisionByZero));
+            let modulo = Modulo::new(Box::new(Number::new(1)), Box::new(Number::new(0)));
+            assert_eq!(modulo.evaluate(&env), Err(EvalError::DivisionByZero));
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_modes() {
+        let max_plus_one = Plus::new(Box::new(Number::new(i32::MAX)), Box::new(Number::new(1)));
+        let min_div_neg = Divide::new(Box::new(Number::new(i32::MIN)), Box::new(Number::new(-1)));
+        let negate_min = Negate::new(Box::new(Number::new(i32::MIN)));
+
+        let checked = Environment::new();
+        assert_eq!(max_plus_one.evaluate(&checked), Err(EvalError::Overflow));
+        assert_eq!(min_div_neg.evaluate(&checked), Err(EvalError::Overflow));
+        assert_eq!(negate_min.evaluate(&checked), Err(EvalError::Overflow));
+
+        let wrapping = Environment::new().with_mode(ArithmeticMode::Wrapping);
+        assert_eq!(max_plus_one.evaluate(&wrapping), Ok(i32::MIN));
+        assert_eq!(min_div_neg.evaluate(&wrapping), Ok(i32::MIN));
+        assert_eq!(negate_min.evaluate(&wrapping), Ok(i32::MIN));
+
+        let saturating = Environment::new().with_mode(ArithmeticMode::Saturating);
+        assert_eq!(max_plus_one.evaluate(&saturating), Ok(i32::MAX));
+        assert_eq!(min_div_neg.evaluate(&saturating), Ok(i32::MAX));
+        assert_eq!(negate_min.evaluate(&saturating), Ok(i32::MAX));
+    }
+
+    #[test]
+    fn test_mode_inherited_by_let_scope() {
+        let env = Environment::new().with_mode(ArithmeticMode::Saturating);
+        let expr = Let::new(
+            "x",
+            Box::new(Number::new(i32::MAX)),
+            Box::new(Times::new(Box::new(Variable::new("x")), Box::new(Number::new(2)))),
+        );
+        assert_eq!(expr.evaluate(&env), Ok(i32::MAX));
+    }
+
     #[test]
     fn test_complex() {
         let expr = Plus::new(
@@ -98,4 +544,36 @@ mod tests {
         );
         assert_eq!(expr.interpret(), 9);
     }
+
+    #[test]
+    fn test_depth_is_capped_at_parse_time() {
+        use parser::{parse, ParseErrorKind, MAX_DEPTH};
+
+        let sum = vec!["1"; 100_000].join(" + ");
+        assert!(matches!(parse(&sum), Err(e) if e.kind == ParseErrorKind::TooDeep));
+        let product = format!("{}x", "2 * (".repeat(50_000));
+        assert!(matches!(parse(&product), Err(e) if e.kind == ParseErrorKind::TooDeep));
+
+        let sum = vec!["1"; MAX_DEPTH + 1].join(" + ");
+        let expr = parse(&sum).unwrap();
+        assert_eq!(expr.interpret(), MAX_DEPTH as i32 + 1);
+        assert_eq!(expr.to_string(), sum);
+        assert!(bytecode::compile(expr.as_ref()).is_ok());
+        let too_long = format!("{} + 1", sum);
+        assert!(matches!(parse(&too_long), Err(e) if e.kind == ParseErrorKind::TooDeep));
+    }
+
+    #[test]
+    fn test_try_interpret_reports_errors() {
+        let overflow = Plus::new(Box::new(Number::new(i32::MAX)), Box::new(Number::new(1)));
+        assert_eq!(overflow.try_interpret(), Err(EvalError::Overflow));
+        let unbound = Plus::new(Box::new(Variable::new("x")), Box::new(Number::new(1)));
+        assert_eq!(unbound.try_interpret(), Err(EvalError::UnboundVariable("x".into())));
+    }
+
+    #[test]
+    #[should_panic(expected = "unbound variable 'x'")]
+    fn test_interpret_panics_on_error() {
+        Variable::new("x").interpret();
+    }
 }
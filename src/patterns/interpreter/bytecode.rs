@@ -0,0 +1,406 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{BinaryOp, Environment, EvalError, Expression, ExpressionVisitor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Const(i32),
+    Input(usize),
+    Load(usize),
+    Store(usize),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Neg,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramError {
+    StackUnderflow { at: usize },
+    UnknownLocal { at: usize, slot: usize },
+    UnknownInput { at: usize, index: usize },
+    UnbalancedStack { depth: usize },
+    Unsupported { at: usize },
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::StackUnderflow { at } => {
+                write!(f, "instruction {} pops from an empty stack", at)
+            }
+            ProgramError::UnknownLocal { at, slot } => {
+                write!(f, "instruction {} uses local slot {} which was never allocated", at, slot)
+            }
+            ProgramError::UnknownInput { at, index } => {
+                write!(f, "instruction {} reads undeclared input {}", at, index)
+            }
+            ProgramError::UnbalancedStack { depth } => {
+                write!(f, "program leaves {} values on the stack instead of 1", depth)
+            }
+            ProgramError::Unsupported { at } => {
+                write!(f, "instruction {} would need an expression with no bytecode form", at)
+            }
+        }
+    }
+}
+
+impl Error for ProgramError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    code: Vec<Instruction>,
+    inputs: Vec<String>,
+    locals: usize,
+    max_stack: usize,
+}
+
+impl Program {
+    pub fn code(&self) -> &[Instruction] {
+        &self.code
+    }
+
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    // Checks every stack effect and slot reference up front, so `Vm::run` can index without
+    // bounds failures and only has to report evaluation errors.
+    fn validate(&mut self) -> Result<(), ProgramError> {
+        let mut depth = 0usize;
+        self.max_stack = 0;
+        for (at, instruction) in self.code.iter().enumerate() {
+            let (pops, pushes) = match *instruction {
+                Instruction::Const(_) => (0, 1),
+                Instruction::Input(index) => {
+                    if index >= self.inputs.len() {
+                        return Err(ProgramError::UnknownInput { at, index });
+                    }
+                    (0, 1)
+                }
+                Instruction::Load(slot) | Instruction::Store(slot) => {
+                    if slot >= self.locals {
+                        return Err(ProgramError::UnknownLocal { at, slot });
+                    }
+                    if matches!(instruction, Instruction::Load(_)) { (0, 1) } else { (1, 0) }
+                }
+                Instruction::Neg => (1, 1),
+                Instruction::Add
+                | Instruction::Sub
+                | Instruction::Mul
+                | Instruction::Div
+                | Instruction::Rem => (2, 1),
+            };
+            depth = depth.checked_sub(pops).ok_or(ProgramError::StackUnderflow { at })? + pushes;
+            self.max_stack = self.max_stack.max(depth);
+        }
+        if depth != 1 {
+            return Err(ProgramError::UnbalancedStack { depth });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Compiler {
+    code: Vec<Instruction>,
+    inputs: Vec<String>,
+    scopes: Vec<(String, usize)>,
+    locals: usize,
+    unsupported: Option<usize>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn emit(&mut self, instruction: Instruction) {
+        self.code.push(instruction);
+    }
+
+    pub fn variable(&mut self, name: &str) {
+        if let Some((_, slot)) = self.scopes.iter().rev().find(|(bound, _)| bound == name) {
+            self.emit(Instruction::Load(*slot));
+            return;
+        }
+        let index = match self.inputs.iter().position(|input| input == name) {
+            Some(index) => index,
+            None => {
+                self.inputs.push(name.to_string());
+                self.inputs.len() - 1
+            }
+        };
+        self.emit(Instruction::Input(index));
+    }
+
+    pub fn begin_scope(&mut self, name: &str) {
+        let slot = self.locals;
+        self.locals += 1;
+        self.emit(Instruction::Store(slot));
+        self.scopes.push((name.to_string(), slot));
+    }
+
+    pub fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn finish(self) -> Result<Program, ProgramError> {
+        if let Some(at) = self.unsupported {
+            return Err(ProgramError::Unsupported { at });
+        }
+        let mut program =
+            Program { code: self.code, inputs: self.inputs, locals: self.locals, max_stack: 0 };
+        program.validate()?;
+        Ok(program)
+    }
+}
+
+impl ExpressionVisitor for Compiler {
+    fn visit_number(&mut self, value: i32) {
+        self.emit(Instruction::Const(value));
+    }
+
+    fn visit_variable(&mut self, name: &str) {
+        self.variable(name);
+    }
+
+    fn visit_binary(&mut self, op: BinaryOp, left: &dyn Expression, right: &dyn Expression) {
+        left.accept(self);
+        right.accept(self);
+        self.emit(match op {
+            BinaryOp::Add => Instruction::Add,
+            BinaryOp::Sub => Instruction::Sub,
+            BinaryOp::Mul => Instruction::Mul,
+            BinaryOp::Div => Instruction::Div,
+            BinaryOp::Rem => Instruction::Rem,
+        });
+    }
+
+    fn visit_negate(&mut self, operand: &dyn Expression) {
+        operand.accept(self);
+        self.emit(Instruction::Neg);
+    }
+
+    fn visit_let(&mut self, name: &str, value: &dyn Expression, body: &dyn Expression) {
+        value.accept(self);
+        self.begin_scope(name);
+        body.accept(self);
+        self.end_scope();
+    }
+
+    fn visit_opaque(&mut self) {
+        self.unsupported.get_or_insert(self.code.len());
+    }
+}
+
+pub fn compile(expr: &dyn Expression) -> Result<Program, ProgramError> {
+    let mut compiler = Compiler::new();
+    expr.accept(&mut compiler);
+    compiler.finish()
+}
+
+#[derive(Default)]
+pub struct Vm {
+    stack: Vec<i32>,
+    locals: Vec<i32>,
+    inputs: Vec<Option<i32>>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn run(&mut self, program: &Program, env: &Environment) -> Result<i32, EvalError> {
+        let mode = env.mode();
+        self.stack.clear();
+        self.stack.reserve(program.max_stack);
+        self.locals.clear();
+        self.locals.resize(program.locals, 0);
+        self.inputs.clear();
+        self.inputs.extend(program.inputs.iter().map(|name| env.lookup(name)));
+
+        // `Program` values only come out of `Compiler::finish`, which validated every index and
+        // stack effect, so the indexing and pops below can't fail.
+        for instruction in &program.code {
+            let value = match *instruction {
+                Instruction::Const(value) => value,
+                Instruction::Input(index) => match self.inputs[index] {
+                    Some(value) => value,
+                    None => return Err(EvalError::UnboundVariable(program.inputs[index].clone())),
+                },
+                Instruction::Load(slot) => self.locals[slot],
+                Instruction::Store(slot) => {
+                    self.locals[slot] = self.pop();
+                    continue;
+                }
+                Instruction::Neg => {
+                    let operand = self.pop();
+                    mode.neg(operand)?
+                }
+                Instruction::Add => self.binary(|a, b| mode.add(a, b))?,
+                Instruction::Sub => self.binary(|a, b| mode.sub(a, b))?,
+                Instruction::Mul => self.binary(|a, b| mode.mul(a, b))?,
+                Instruction::Div => self.binary(|a, b| mode.div(a, b))?,
+                Instruction::Rem => self.binary(|a, b| mode.rem(a, b))?,
+            };
+            self.stack.push(value);
+        }
+        Ok(self.pop())
+    }
+
+    fn pop(&mut self) -> i32 {
+        self.stack.pop().unwrap_or_default()
+    }
+
+    fn binary(
+        &mut self,
+        op: impl FnOnce(i32, i32) -> Result<i32, EvalError>,
+    ) -> Result<i32, EvalError> {
+        let right = self.pop();
+        let left = self.pop();
+        op(left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::parse;
+    use super::super::ArithmeticMode;
+    use super::*;
+
+    #[test]
+    fn test_compiles_to_postfix() {
+        let program = compile(parse("x - 4 * 2").unwrap().as_ref()).unwrap();
+        assert_eq!(
+            program.code(),
+            &[
+                Instruction::Input(0),
+                Instruction::Const(4),
+                Instruction::Const(2),
+                Instruction::Mul,
+                Instruction::Sub,
+            ]
+        );
+        assert_eq!(program.inputs(), &["x".to_string()]);
+    }
+
+    #[test]
+    fn test_vm_reuse_across_inputs() {
+        let program = compile(parse("let y = x * x in y + x").unwrap().as_ref()).unwrap();
+        let mut vm = Vm::new();
+        for x in -5..5 {
+            let env = Environment::new().with("x", x);
+            assert_eq!(vm.run(&program, &env), Ok(x * x + x));
+        }
+        assert_eq!(
+            vm.run(&program, &Environment::new()),
+            Err(EvalError::UnboundVariable("x".into()))
+        );
+    }
+
+    fn assemble(code: &[Instruction], inputs: usize, locals: usize) -> Result<(), ProgramError> {
+        let mut compiler = Compiler::new();
+        compiler.inputs = (0..inputs).map(|i| format!("in{}", i)).collect();
+        compiler.locals = locals;
+        for instruction in code {
+            compiler.emit(*instruction);
+        }
+        compiler.finish().map(|_| ())
+    }
+
+    #[test]
+    fn test_rejects_malformed_programs() {
+        use Instruction::*;
+        use ProgramError::*;
+        assert_eq!(assemble(&[Const(1), Add], 0, 0), Err(StackUnderflow { at: 1 }));
+        assert_eq!(assemble(&[Load(99)], 0, 1), Err(UnknownLocal { at: 0, slot: 99 }));
+        assert_eq!(assemble(&[Input(3)], 1, 0), Err(UnknownInput { at: 0, index: 3 }));
+        assert_eq!(assemble(&[Const(1), Store(0)], 0, 1), Err(UnbalancedStack { depth: 0 }));
+        assert_eq!(assemble(&[Const(1), Const(2)], 0, 0), Err(UnbalancedStack { depth: 2 }));
+        assert_eq!(assemble(&[Const(1), Store(0), Load(0), Neg], 0, 1), Ok(()));
+    }
+
+    struct Opaque;
+
+    impl Expression for Opaque {
+        fn evaluate(&self, _env: &Environment) -> Result<i32, EvalError> {
+            Ok(7)
+        }
+    }
+
+    #[test]
+    fn test_opaque_expressions_are_rejected() {
+        use super::super::{Number, Plus};
+
+        let expr = Plus::new(Box::new(Number::new(1)), Box::new(Opaque));
+        assert_eq!(expr.evaluate(&Environment::new()), Ok(8));
+        assert_eq!(compile(&expr), Err(ProgramError::Unsupported { at: 1 }));
+    }
+
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    fn random_source(rng: &mut Rng, depth: u32) -> String {
+        let names = ["a", "b", "c", "z"];
+        if depth == 0 || rng.below(4) == 0 {
+            return match rng.below(3) {
+                0 => names[rng.below(names.len() as u64) as usize].to_string(),
+                1 => [0, 1, -1, i32::MAX, i32::MIN][rng.below(5) as usize].to_string(),
+                _ => (rng.below(200) as i32 - 100).to_string(),
+            };
+        }
+        let left = random_source(rng, depth - 1);
+        let right = random_source(rng, depth - 1);
+        match rng.below(7) {
+            0 => format!("({} + {})", left, right),
+            1 => format!("({} - {})", left, right),
+            2 => format!("({} * {})", left, right),
+            3 => format!("({} / {})", left, right),
+            4 => format!("({} % {})", left, right),
+            5 => format!("-({})", left),
+            _ => format!("(let {} = {} in {})", names[rng.below(3) as usize], left, right),
+        }
+    }
+
+    #[test]
+    fn test_vm_agrees_with_tree_walker() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        let mut vm = Vm::new();
+        let modes = [ArithmeticMode::Checked, ArithmeticMode::Wrapping, ArithmeticMode::Saturating];
+        for _ in 0..2000 {
+            let source = random_source(&mut rng, 5);
+            let expr = parse(&source).unwrap();
+            let program = compile(expr.as_ref()).unwrap();
+            for mode in modes {
+                let env = Environment::new()
+                    .with_mode(mode)
+                    .with("a", rng.below(20) as i32 - 10)
+                    .with("b", i32::MAX);
+                assert_eq!(
+                    vm.run(&program, &env),
+                    expr.evaluate(&env),
+                    "{:?} disagreement on {}",
+                    mode,
+                    source
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{Divide, Expression, Let, Minus, Modulo, Negate, Number, Plus, Times, Variable};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedChar(char),
+    Expected { expected: &'static str, found: String },
+    NumberOutOfRange(String),
+    TooDeep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "unexpected character '{}'", c),
+            ParseErrorKind::Expected { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ParseErrorKind::NumberOutOfRange(text) => {
+                write!(f, "number '{}' is out of range", text)
+            }
+            ParseErrorKind::TooDeep => {
+                write!(f, "expression is nested more than {} levels deep", MAX_DEPTH)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(String),
+    Ident(String),
+    Let,
+    In,
+    Equals,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    LParen,
+    RParen,
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(text) => format!("number '{}'", text),
+            Token::Ident(name) => format!("identifier '{}'", name),
+            Token::Let => "'let'".to_string(),
+            Token::In => "'in'".to_string(),
+            Token::Equals => "'='".to_string(),
+            Token::Plus => "'+'".to_string(),
+            Token::Minus => "'-'".to_string(),
+            Token::Star => "'*'".to_string(),
+            Token::Slash => "'/'".to_string(),
+            Token::Percent => "'%'".to_string(),
+            Token::LParen => "'('".to_string(),
+            Token::RParen => "')'".to_string(),
+            Token::End => "end of input".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pos {
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Pos)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut pos = Pos { line: 1, column: 1 };
+    while let Some(&c) = chars.peek() {
+        let start = pos;
+        if c.is_ascii_digit() {
+            let mut text = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                text.push(d);
+                chars.next();
+                pos.column += 1;
+            }
+            tokens.push((Token::Number(text), start));
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let mut text = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_alphanumeric() || **d == '_') {
+                text.push(d);
+                chars.next();
+                pos.column += 1;
+            }
+            let token = match text.as_str() {
+                "let" => Token::Let,
+                "in" => Token::In,
+                _ => Token::Ident(text),
+            };
+            tokens.push((token, start));
+            continue;
+        }
+        chars.next();
+        if c == '\n' {
+            pos.line += 1;
+            pos.column = 1;
+            continue;
+        }
+        pos.column += 1;
+        let token = match c {
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '=' => Token::Equals,
+            c if c.is_whitespace() => continue,
+            c => {
+                let kind = ParseErrorKind::UnexpectedChar(c);
+                return Err(ParseError { kind, line: start.line, column: start.column });
+            }
+        };
+        tokens.push((token, start));
+    }
+    tokens.push((Token::End, pos));
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, Pos)>,
+    index: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn advance(&mut self) -> (Token, Pos) {
+        let item = self.tokens[self.index].clone();
+        if item.0 != Token::End {
+            self.index += 1;
+        }
+        item
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        let pos = self.tokens[self.index].1;
+        ParseError { kind, line: pos.line, column: pos.column }
+    }
+
+    fn expected(&self, expected: &'static str) -> ParseError {
+        self.error(ParseErrorKind::Expected { expected, found: self.peek().describe() })
+    }
+
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<(), ParseError> {
+        if *self.peek() != token {
+            return Err(self.expected(expected));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    // Parsing recurses once per level of nesting, so bound it before a pathological input such
+    // as thousands of open parentheses runs the stack out.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.descend()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    // Each operator in a chain such as `1 + 2 + 3` puts the tree built so far one level deeper,
+    // so operators count towards the limit as well. Evaluating, compiling and printing all
+    // recurse over the tree and rely on this bound.
+    fn operator(&mut self) -> Result<(), ParseError> {
+        self.descend()?;
+        self.advance();
+        Ok(())
+    }
+
+    fn descend(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeep));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn expression(&mut self) -> Result<Box<dyn Expression>, ParseError> {
+        if *self.peek() != Token::Let {
+            return self.additive();
+        }
+        self.advance();
+        let Token::Ident(name) = self.peek().clone() else {
+            return Err(self.expected("an identifier"));
+        };
+        self.advance();
+        self.expect(Token::Equals, "'='")?;
+        let value = self.nested(Self::expression)?;
+        self.expect(Token::In, "'in'")?;
+        let body = self.nested(Self::expression)?;
+        Ok(Box::new(Let::new(name, value, body)))
+    }
+
+    fn additive(&mut self) -> Result<Box<dyn Expression>, ParseError> {
+        let outer = self.depth;
+        let mut left = self.multiplicative()?;
+        loop {
+            match self.peek() {
+                Token::Plus => {
+                    self.operator()?;
+                    left = Box::new(Plus::new(left, self.multiplicative()?));
+                }
+                Token::Minus => {
+                    self.operator()?;
+                    left = Box::new(Minus::new(left, self.multiplicative()?));
+                }
+                _ => break,
+            }
+        }
+        self.depth = outer;
+        Ok(left)
+    }
+
+    fn multiplicative(&mut self) -> Result<Box<dyn Expression>, ParseError> {
+        let outer = self.depth;
+        let mut left = self.unary()?;
+        loop {
+            match self.peek() {
+                Token::Star => {
+                    self.operator()?;
+                    left = Box::new(Times::new(left, self.unary()?));
+                }
+                Token::Slash => {
+                    self.operator()?;
+                    left = Box::new(Divide::new(left, self.unary()?));
+                }
+                Token::Percent => {
+                    self.operator()?;
+                    left = Box::new(Modulo::new(left, self.unary()?));
+                }
+                _ => break,
+            }
+        }
+        self.depth = outer;
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Box<dyn Expression>, ParseError> {
+        if *self.peek() != Token::Minus {
+            return self.primary();
+        }
+        self.advance();
+        if let Token::Number(text) = self.peek().clone() {
+            let value = self.number(&format!("-{}", text))?;
+            self.advance();
+            return Ok(Box::new(Number::new(value)));
+        }
+        Ok(Box::new(Negate::new(self.nested(Self::unary)?)))
+    }
+
+    fn primary(&mut self) -> Result<Box<dyn Expression>, ParseError> {
+        match self.peek().clone() {
+            Token::Number(text) => {
+                let value = self.number(&text)?;
+                self.advance();
+                Ok(Box::new(Number::new(value)))
+            }
+            Token::Ident(name) => {
+                self.advance();
+                Ok(Box::new(Variable::new(name)))
+            }
+            Token::LParen => {
+                self.advance();
+                let inner = self.nested(Self::expression)?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(inner)
+            }
+            _ => Err(self.expected("a number, variable or '('")),
+        }
+    }
+
+    fn number(&self, text: &str) -> Result<i32, ParseError> {
+        text.parse().map_err(|_| self.error(ParseErrorKind::NumberOutOfRange(text.to_string())))
+    }
+}
+
+pub fn parse(source: &str) -> Result<Box<dyn Expression>, ParseError> {
+    let mut parser = Parser { tokens: tokenize(source)?, index: 0, depth: 0 };
+    let expr = parser.expression()?;
+    if *parser.peek() != Token::End {
+        return Err(parser.expected("an operator or end of input"));
+    }
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> i32 {
+        parse(source).unwrap().interpret()
+    }
+
+    fn parse_err(source: &str) -> ParseError {
+        match parse(source) {
+            Err(e) => e,
+            Ok(_) => panic!("Expected a parse error for {:?}", source),
+        }
+    }
+
+    #[test]
+    fn test_left_associative() {
+        assert_eq!(eval("10 - 4 + 3"), 9);
+        assert_eq!(eval("10 - 4 - 3"), 3);
+    }
+
+    #[test]
+    fn test_multiplicative_precedence() {
+        assert_eq!(eval("2 + 3 * 4"), 14);
+        assert_eq!(eval("(2 + 3) * 4"), 20);
+        assert_eq!(eval("20 / 3 % 4 * -2"), -4);
+        assert_eq!(eval("1 - 6 / 2 / 3"), 0);
+    }
+
+    #[test]
+    fn test_parentheses_and_unary_minus() {
+        assert_eq!(eval("10 - (4 + 3)"), 3);
+        assert_eq!(eval("-(2 + 3) - -4"), -1);
+        assert_eq!(eval("--5"), 5);
+        assert_eq!(eval("-2147483648"), i32::MIN);
+    }
+
+    #[test]
+    fn test_variables_and_let() {
+        use super::super::{Environment, EvalError};
+
+        let env = Environment::new().with("x", 5);
+        let expr = parse("let y = x - 1 in (let x = 10 in x + y) - x").unwrap();
+        assert_eq!(expr.evaluate(&env), Ok(9));
+        let expr = parse("x + missing").unwrap();
+        assert_eq!(expr.evaluate(&env), Err(EvalError::UnboundVariable("missing".into())));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let err = parse_err("1 +\n  (2 - )");
+        assert_eq!((err.line, err.column), (2, 8));
+        assert!(matches!(err.kind, ParseErrorKind::Expected { .. }));
+
+        let err = parse_err("1 $ 2");
+        assert_eq!(err.kind, ParseErrorKind::UnexpectedChar('$'));
+        assert_eq!((err.line, err.column), (1, 3));
+
+        let err = parse_err("(1 + 2");
+        assert_eq!((err.line, err.column), (1, 7));
+
+        let err = parse_err("let 1 = 2 in 3");
+        assert_eq!((err.line, err.column), (1, 5));
+
+        let err = parse_err("99999999999");
+        assert!(matches!(err.kind, ParseErrorKind::NumberOutOfRange(_)));
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let levels = 100_000;
+        let parens = format!("{}1{}", "(".repeat(levels), ")".repeat(levels));
+        assert_eq!(parse_err(&parens).kind, ParseErrorKind::TooDeep);
+        assert_eq!(parse_err(&format!("{}x", "-".repeat(levels))).kind, ParseErrorKind::TooDeep);
+        let lets = "let x = 1 in ".repeat(levels) + "x";
+        assert_eq!(parse_err(&lets).kind, ParseErrorKind::TooDeep);
+
+        let source = format!("{}1{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert_eq!(eval(&source), 1);
+        assert_eq!(eval(&format!("{}1", "-".repeat(MAX_DEPTH))), 1);
+    }
+}
@@ -0,0 +1,266 @@
+use std::fmt::Write;
+use std::mem;
+
+use super::{BinaryOp, Expression, ExpressionVisitor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    Let,
+    Additive,
+    Multiplicative,
+    Unary,
+}
+
+impl Precedence {
+    fn tighter(self) -> Self {
+        match self {
+            Precedence::Let => Precedence::Additive,
+            Precedence::Additive => Precedence::Multiplicative,
+            Precedence::Multiplicative | Precedence::Unary => Precedence::Unary,
+        }
+    }
+}
+
+pub struct Printer {
+    out: String,
+    min: Precedence,
+    after_minus: bool,
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Printer { out: String::new(), min: Precedence::Let, after_minus: false }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    pub fn number(&mut self, value: i32) {
+        if mem::take(&mut self.after_minus) && value >= 0 {
+            write!(self.out, "({})", value).unwrap();
+        } else {
+            write!(self.out, "{}", value).unwrap();
+        }
+    }
+
+    pub fn variable(&mut self, name: &str) {
+        self.after_minus = false;
+        self.out.push_str(name);
+    }
+
+    pub fn infix(
+        &mut self,
+        operator: &str,
+        precedence: Precedence,
+        left: &dyn Expression,
+        right: &dyn Expression,
+    ) {
+        self.group(precedence, |p| {
+            p.child(left, precedence);
+            write!(p.out, " {} ", operator).unwrap();
+            p.child(right, precedence.tighter());
+        });
+    }
+
+    pub fn negate(&mut self, operand: &dyn Expression) {
+        self.group(Precedence::Unary, |p| {
+            p.out.push('-');
+            p.after_minus = true;
+            p.child(operand, Precedence::Unary);
+        });
+    }
+
+    pub fn let_in(&mut self, name: &str, value: &dyn Expression, body: &dyn Expression) {
+        self.group(Precedence::Let, |p| {
+            write!(p.out, "let {} = ", name).unwrap();
+            p.child(value, Precedence::Additive);
+            p.out.push_str(" in ");
+            p.child(body, Precedence::Let);
+        });
+    }
+
+    fn child(&mut self, expr: &dyn Expression, min: Precedence) {
+        let saved = mem::replace(&mut self.min, min);
+        expr.accept(self);
+        self.min = saved;
+    }
+
+    fn group(&mut self, precedence: Precedence, body: impl FnOnce(&mut Self)) {
+        self.after_minus = false;
+        let parens = precedence < self.min;
+        let saved = self.min;
+        if parens {
+            self.out.push('(');
+            self.min = Precedence::Let;
+        }
+        body(self);
+        if parens {
+            self.out.push(')');
+        }
+        self.min = saved;
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExpressionVisitor for Printer {
+    fn visit_number(&mut self, value: i32) {
+        self.number(value);
+    }
+
+    fn visit_variable(&mut self, name: &str) {
+        self.variable(name);
+    }
+
+    fn visit_binary(&mut self, op: BinaryOp, left: &dyn Expression, right: &dyn Expression) {
+        let (operator, precedence) = match op {
+            BinaryOp::Add => ("+", Precedence::Additive),
+            BinaryOp::Sub => ("-", Precedence::Additive),
+            BinaryOp::Mul => ("*", Precedence::Multiplicative),
+            BinaryOp::Div => ("/", Precedence::Multiplicative),
+            BinaryOp::Rem => ("%", Precedence::Multiplicative),
+        };
+        self.infix(operator, precedence, left, right);
+    }
+
+    fn visit_negate(&mut self, operand: &dyn Expression) {
+        self.negate(operand);
+    }
+
+    fn visit_let(&mut self, name: &str, value: &dyn Expression, body: &dyn Expression) {
+        self.let_in(name, value, body);
+    }
+
+    fn visit_opaque(&mut self) {
+        self.after_minus = false;
+        self.out.push_str("<opaque>");
+    }
+}
+
+pub fn to_source(expr: &dyn Expression) -> String {
+    let mut printer = Printer::new();
+    expr.accept(&mut printer);
+    printer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::parse;
+    use super::super::*;
+    use super::*;
+
+    fn num(value: i32) -> Box<dyn Expression> {
+        Box::new(Number::new(value))
+    }
+
+    fn var(name: &str) -> Box<dyn Expression> {
+        Box::new(Variable::new(name))
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        let expr = Minus::new(num(10), Box::new(Plus::new(num(4), num(3))));
+        assert_eq!(to_source(&expr), "10 - (4 + 3)");
+        let expr = Plus::new(Box::new(Minus::new(num(10), num(4))), num(3));
+        assert_eq!(to_source(&expr), "10 - 4 + 3");
+        let sum = Box::new(Plus::new(var("a"), num(1)));
+        let expr = Times::new(sum, Box::new(Divide::new(var("b"), num(2))));
+        assert_eq!(to_source(&expr), "(a + 1) * (b / 2)");
+        let rem = Box::new(Modulo::new(var("b"), num(-2)));
+        let expr: Box<dyn Expression> = Box::new(Plus::new(var("a"), rem));
+        assert_eq!(expr.to_string(), "a + b % -2");
+    }
+
+    #[test]
+    fn test_negation_and_let() {
+        assert_eq!(to_source(&Negate::new(num(5))), "-(5)");
+        assert_eq!(to_source(&Negate::new(num(-5))), "--5");
+        assert_eq!(to_source(&Negate::new(Box::new(Plus::new(var("x"), num(1))))), "-(x + 1)");
+        let binding = Let::new("x", Box::new(Plus::new(num(1), num(2))), var("x"));
+        assert_eq!(to_source(&binding), "let x = 1 + 2 in x");
+        let expr = Times::new(Box::new(binding), num(2));
+        assert_eq!(to_source(&expr), "(let x = 1 + 2 in x) * 2");
+    }
+
+    struct Opaque;
+
+    impl Expression for Opaque {
+        fn evaluate(&self, _env: &Environment) -> Result<i32, EvalError> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_opaque_placeholder() {
+        assert_eq!(to_source(&Minus::new(num(1), Box::new(Opaque))), "1 - <opaque>");
+        assert_eq!(to_source(&Negate::new(Box::new(Opaque))), "-<opaque>");
+    }
+
+    // Fully parenthesised prefix form, so two trees compare equal only if they have the same shape.
+    #[derive(Default)]
+    struct Shape(String);
+
+    impl ExpressionVisitor for Shape {
+        fn visit_number(&mut self, value: i32) {
+            write!(self.0, "{} ", value).unwrap();
+        }
+
+        fn visit_variable(&mut self, name: &str) {
+            write!(self.0, "{} ", name).unwrap();
+        }
+
+        fn visit_binary(&mut self, op: BinaryOp, left: &dyn Expression, right: &dyn Expression) {
+            write!(self.0, "({:?} ", op).unwrap();
+            left.accept(self);
+            right.accept(self);
+            self.0.push_str(") ");
+        }
+
+        fn visit_negate(&mut self, operand: &dyn Expression) {
+            self.0.push_str("(neg ");
+            operand.accept(self);
+            self.0.push_str(") ");
+        }
+
+        fn visit_let(&mut self, name: &str, value: &dyn Expression, body: &dyn Expression) {
+            write!(self.0, "(let {} ", name).unwrap();
+            value.accept(self);
+            body.accept(self);
+            self.0.push_str(") ");
+        }
+
+        fn visit_opaque(&mut self) {
+            self.0.push_str("? ");
+        }
+    }
+
+    fn shape(expr: &dyn Expression) -> String {
+        let mut shape = Shape::default();
+        expr.accept(&mut shape);
+        shape.0
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            "10 - 4 + 3",
+            "10 - (4 + 3)",
+            "-(5) * --x",
+            "-2147483648 - -(2147483647)",
+            "let y = (let x = 2 in x * x) in y % (y - 1) / -y",
+            "a - (b - (c - d)) * ((e + f) % g)",
+        ];
+        for source in sources {
+            let expr = parse(source).unwrap();
+            let printed = to_source(expr.as_ref());
+            assert_eq!(printed, source);
+            let reparsed = parse(&printed).unwrap();
+            assert_eq!(shape(reparsed.as_ref()), shape(expr.as_ref()), "{}", source);
+        }
+    }
+}
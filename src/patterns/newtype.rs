@@ -15,9 +15,9 @@ impl From<u64> for UserId {
     }
 }
 
-impl Into<u64> for UserId {
-    fn into(self) -> u64 {
-        self.0
+impl From<UserId> for u64 {
+    fn from(id: UserId) -> Self {
+        id.0
     }
 }
 
@@ -1,32 +1,202 @@
-use std::collections::HashMap;
+pub mod stream;
+pub mod sync;
 
-pub struct EventEmitter<E> {
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use stream::{EventStream, Stream, StreamOptions};
+use sync::SyncEventEmitter;
+
+type Handler<E, R> = Box<dyn FnMut(&E) -> R>;
+
+struct Observer<E, R> {
+    id: u64,
+    priority: i32,
+    // Shared with the observer's `Subscription`, so dropping the guard can switch the handler
+    // off without borrowing the emitter.
+    active: Rc<Cell<bool>>,
+    handler: RefCell<Handler<E, R>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Stop,
+    Unsubscribe,
+}
+
+pub struct EventEmitter<E, R = ()> {
     next_id: u64,
-    observers: HashMap<u64, Box<dyn Fn(&E)>>,
+    observers: Vec<Observer<E, R>>,
 }
 
-impl<E> EventEmitter<E> {
+impl<E, R> EventEmitter<E, R> {
     pub fn new() -> Self {
-        EventEmitter { next_id: 0, observers: HashMap::new() }
+        EventEmitter { next_id: 0, observers: Vec::new() }
     }
 
     pub fn subscribe<F>(&mut self, handler: F) -> u64
     where
-        F: Fn(&E) + 'static,
+        F: FnMut(&E) -> R + 'static,
     {
+        self.subscribe_with_priority(0, handler)
+    }
+
+    pub fn subscribe_with_priority<F>(&mut self, priority: i32, handler: F) -> u64
+    where
+        F: FnMut(&E) -> R + 'static,
+    {
+        self.insert(priority, Box::new(handler)).0
+    }
+
+    fn insert(&mut self, priority: i32, handler: Handler<E, R>) -> (u64, Rc<Cell<bool>>) {
         let id = self.next_id;
         self.next_id += 1;
-        self.observers.insert(id, Box::new(handler));
-        id
+        self.observers.retain(|o| o.active.get());
+        // Higher priorities run first; equal priorities keep subscription order.
+        let index = self.observers.partition_point(|o| o.priority >= priority);
+        let active = Rc::new(Cell::new(true));
+        let handler = RefCell::new(handler);
+        let observer = Observer { id, priority, active: Rc::clone(&active), handler };
+        self.observers.insert(index, observer);
+        (id, active)
+    }
+
+    pub fn subscribe_guarded<F>(emitter: &Rc<RefCell<Self>>, handler: F) -> Subscription<E, R>
+    where
+        F: FnMut(&E) -> R + 'static,
+    {
+        Self::subscribe_guarded_with_priority(emitter, 0, handler)
+    }
+
+    pub fn subscribe_guarded_with_priority<F>(
+        emitter: &Rc<RefCell<Self>>,
+        priority: i32,
+        handler: F,
+    ) -> Subscription<E, R>
+    where
+        F: FnMut(&E) -> R + 'static,
+    {
+        let (id, active) = emitter.borrow_mut().insert(priority, Box::new(handler));
+        Subscription { emitter: Rc::downgrade(emitter), id, active }
     }
 
     pub fn unsubscribe(&mut self, id: u64) -> bool {
-        self.observers.remove(&id).is_some()
+        self.observers.retain(|o| o.active.get());
+        match self.observers.iter().position(|o| o.id == id) {
+            Some(index) => {
+                self.observers.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.iter().filter(|o| o.active.get()).count()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // `emit` and `emit_collect` treat handler results as plain data; on an
+    // `EventEmitter<E, Control>` use `dispatch` to have them steer propagation.
     pub fn emit(&self, event: &E) {
-        for handler in self.observers.values() {
-            handler(event);
+        self.run(event, |_| Control::Continue);
+    }
+
+    pub fn emit_collect(&self, event: &E) -> Vec<R> {
+        let mut results = Vec::new();
+        self.run(event, |result| {
+            results.push(result);
+            Control::Continue
+        });
+        results
+    }
+
+    // Returns true if propagation was stopped. A handler that re-emits on its own emitter is
+    // already running, so it is skipped for the nested emit rather than re-entered.
+    fn run(&self, event: &E, mut control: impl FnMut(R) -> Control) -> bool {
+        for observer in &self.observers {
+            if !observer.active.get() {
+                continue;
+            }
+            let Ok(mut handler) = observer.handler.try_borrow_mut() else {
+                continue;
+            };
+            let result = handler(event);
+            drop(handler);
+            match control(result) {
+                Control::Continue => {}
+                Control::Stop => return true,
+                Control::Unsubscribe => observer.active.set(false),
+            }
+        }
+        false
+    }
+}
+
+impl<E> EventEmitter<E> {
+    // The sender runs inside `emit`, so with `Overflow::Block` a full buffer makes `emit` wait:
+    // drain the stream on another thread, or `emit` deadlocks. Panics if the capacity is 0.
+    pub fn subscribe_stream(
+        emitter: &Rc<RefCell<Self>>,
+        options: StreamOptions,
+    ) -> (Subscription<E>, EventStream<E>)
+    where
+        E: Clone + 'static,
+    {
+        let (sender, stream) = stream::channel(options);
+        let subscription = Self::subscribe_guarded(emitter, move |e: &E| sender.send(e.clone()));
+        (subscription, stream)
+    }
+}
+
+impl<E> EventEmitter<E, Control> {
+    pub fn dispatch(&self, event: &E) -> bool {
+        self.run(event, |control| control)
+    }
+}
+
+impl<E, R> Default for EventEmitter<E, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[must_use = "dropping a Subscription unsubscribes its handler; call `detach` to keep it"]
+pub struct Subscription<E, R = ()> {
+    emitter: Weak<RefCell<EventEmitter<E, R>>>,
+    id: u64,
+    active: Rc<Cell<bool>>,
+}
+
+impl<E, R> Subscription<E, R> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn detach(mut self) -> u64 {
+        self.emitter = Weak::new();
+        self.id
+    }
+}
+
+impl<E, R> Drop for Subscription<E, R> {
+    fn drop(&mut self) {
+        let Some(emitter) = self.emitter.upgrade() else {
+            return;
+        };
+        // The emitter may be borrowed here: from inside `emit`, or mutably when this guard is
+        // owned by a handler that `subscribe` or `unsubscribe` is dropping. The observer is then
+        // only switched off, and removed by the next `subscribe` or `unsubscribe`.
+        self.active.set(false);
+        if let Ok(mut emitter) = emitter.try_borrow_mut() {
+            emitter.unsubscribe(self.id);
         }
     }
 }
@@ -43,6 +213,9 @@ pub fn demo() {
     let id1 = emitter.subscribe(|e: &Event| {
         println!("[Observer demo] Handler1 got event: {:?}", e);
     });
+    emitter.subscribe_with_priority(10, |e: &Event| {
+        println!("[Observer demo] Priority handler saw {:?} first", e);
+    });
     let _id2 = emitter.subscribe(|e: &Event| {
         if let Event::Data(d) = e {
             println!("[Observer demo] Data handler got: {}", d);
@@ -55,4 +228,317 @@ pub fn demo() {
 
     emitter.unsubscribe(id1);
     emitter.emit(&Event::Data("world".into()));
+
+    let guarded = Rc::new(RefCell::new(EventEmitter::new()));
+    {
+        let _guard = EventEmitter::subscribe_guarded(&guarded, |e: &Event| {
+            println!("[Observer demo] Guarded handler got: {:?}", e);
+        });
+        guarded.borrow().emit(&Event::Started);
+    }
+    guarded.borrow().emit(&Event::Finished);
+    println!("[Observer demo] Guard dropped, {} handlers left", guarded.borrow().len());
+
+    let options = StreamOptions { capacity: 2, ..StreamOptions::default() };
+    let (subscription, mut events) = EventEmitter::subscribe_stream(&guarded, options);
+    for text in ["a", "b", "c"] {
+        guarded.borrow().emit(&Event::Data(text.into()));
+    }
+    drop(subscription);
+    stream::block_on(async {
+        while let Some(item) = events.next().await {
+            match item {
+                Ok(event) => println!("[Observer demo] Stream got: {:?}", event),
+                Err(lagged) => println!("[Observer demo] Stream lagged: {}", lagged),
+            }
+        }
+    });
+
+    let mut bubbling = EventEmitter::new();
+    bubbling.subscribe_with_priority(2, |e: &Event| {
+        println!("[Observer demo] Button saw {:?}", e);
+        Control::Continue
+    });
+    bubbling.subscribe_with_priority(1, |e: &Event| match e {
+        Event::Data(_) => Control::Stop,
+        _ => Control::Continue,
+    });
+    let mut seen = 0;
+    bubbling.subscribe(move |_: &Event| {
+        seen += 1;
+        println!("[Observer demo] Window saw event #{}", seen);
+        Control::Continue
+    });
+    let stopped = bubbling.dispatch(&Event::Data("click".into()));
+    println!("[Observer demo] Click bubbling stopped at panel: {}", stopped);
+    bubbling.dispatch(&Event::Finished);
+
+    let shared = SyncEventEmitter::new();
+    let count = Arc::new(AtomicUsize::new(0));
+    let handler_count = Arc::clone(&count);
+    shared.subscribe(move |_: &Event| {
+        handler_count.fetch_add(1, Ordering::SeqCst);
+    });
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| shared.emit(&Event::Started));
+        }
+    });
+    let runs = count.load(Ordering::SeqCst);
+    println!("[Observer demo] Shared handler ran {} times across threads", runs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::stream::Overflow;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn record(
+        emitter: &mut EventEmitter<u32>,
+        log: &Rc<RefCell<Vec<String>>>,
+        name: &'static str,
+        priority: Option<i32>,
+    ) -> u64 {
+        let log = Rc::clone(log);
+        let handler = move |e: &u32| log.borrow_mut().push(format!("{}:{}", name, e));
+        match priority {
+            Some(priority) => emitter.subscribe_with_priority(priority, handler),
+            None => emitter.subscribe(handler),
+        }
+    }
+
+    #[test]
+    fn test_emit_keeps_subscription_order() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut emitter = EventEmitter::new();
+        let names = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let ids: Vec<u64> = names.iter().map(|n| record(&mut emitter, &log, n, None)).collect();
+        emitter.emit(&1);
+        assert_eq!(log.borrow().join(" "), "a:1 b:1 c:1 d:1 e:1 f:1 g:1 h:1");
+
+        log.borrow_mut().clear();
+        assert!(emitter.unsubscribe(ids[2]));
+        assert!(!emitter.unsubscribe(ids[2]));
+        record(&mut emitter, &log, "i", None);
+        emitter.emit(&2);
+        assert_eq!(log.borrow().join(" "), "a:2 b:2 d:2 e:2 f:2 g:2 h:2 i:2");
+    }
+
+    #[test]
+    fn test_higher_priority_runs_first() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut emitter = EventEmitter::new();
+        record(&mut emitter, &log, "default", None);
+        record(&mut emitter, &log, "low", Some(-5));
+        record(&mut emitter, &log, "high", Some(10));
+        record(&mut emitter, &log, "zero", Some(0));
+        record(&mut emitter, &log, "high2", Some(10));
+        emitter.emit(&7);
+        assert_eq!(log.borrow().join(" "), "high:7 high2:7 default:7 zero:7 low:7");
+    }
+
+    fn counting(
+        emitter: &Rc<RefCell<EventEmitter<u32>>>,
+        count: &Rc<Cell<u32>>,
+    ) -> Subscription<u32> {
+        let count = Rc::clone(count);
+        EventEmitter::subscribe_guarded(emitter, move |e| count.set(count.get() + e))
+    }
+
+    #[test]
+    fn test_guard_unsubscribes_on_drop() {
+        let emitter = Rc::new(RefCell::new(EventEmitter::new()));
+        let count = Rc::new(Cell::new(0));
+        let guard = counting(&emitter, &count);
+        let other = counting(&emitter, &count);
+        emitter.borrow().emit(&1);
+        assert_eq!(count.get(), 2);
+
+        drop(guard);
+        emitter.borrow().emit(&1);
+        assert_eq!(count.get(), 3);
+        assert_eq!(emitter.borrow().len(), 1);
+        assert!(emitter.borrow_mut().unsubscribe(other.id()));
+    }
+
+    #[test]
+    fn test_detached_guard_keeps_handler() {
+        let emitter = Rc::new(RefCell::new(EventEmitter::new()));
+        let count = Rc::new(Cell::new(0));
+        let id = counting(&emitter, &count).detach();
+        emitter.borrow().emit(&5);
+        assert_eq!(count.get(), 5);
+        assert!(emitter.borrow_mut().unsubscribe(id));
+    }
+
+    #[test]
+    fn test_guard_outlives_emitter() {
+        let emitter = Rc::new(RefCell::new(EventEmitter::new()));
+        let count = Rc::new(Cell::new(0));
+        let guard = counting(&emitter, &count);
+        drop(emitter);
+        assert_eq!(guard.id(), 0);
+        drop(guard);
+    }
+
+    #[test]
+    fn test_guard_dropped_during_emit() {
+        let emitter = Rc::new(RefCell::new(EventEmitter::new()));
+        let count = Rc::new(Cell::new(0));
+        let slot: Rc<RefCell<Option<Subscription<u32>>>> = Rc::new(RefCell::new(None));
+        let taken = Rc::clone(&slot);
+        let _dropper = EventEmitter::subscribe_guarded_with_priority(&emitter, 1, move |_| {
+            taken.borrow_mut().take();
+        });
+        *slot.borrow_mut() = Some(counting(&emitter, &count));
+
+        emitter.borrow().emit(&1);
+        emitter.borrow().emit(&1);
+        assert_eq!(count.get(), 0);
+        assert_eq!(emitter.borrow().len(), 1);
+        emitter.borrow_mut().subscribe(|_| {});
+        assert_eq!(emitter.borrow().observers.len(), 2);
+    }
+
+    #[test]
+    fn test_guard_dropped_while_emitter_is_mutably_borrowed() {
+        let emitter = Rc::new(RefCell::new(EventEmitter::new()));
+        let count = Rc::new(Cell::new(0));
+        // The outer handler owns the inner guard, so removing it drops that guard while
+        // `unsubscribe` still has the emitter mutably borrowed.
+        let owner = |inner: Subscription<u32>| {
+            EventEmitter::subscribe_guarded(&emitter, move |_| {
+                let _ = &inner;
+            })
+        };
+
+        drop(owner(counting(&emitter, &count)));
+        emitter.borrow().emit(&1);
+        assert_eq!(count.get(), 0);
+        assert_eq!(emitter.borrow().len(), 0);
+
+        let id = owner(counting(&emitter, &count)).detach();
+        assert!(emitter.borrow_mut().unsubscribe(id));
+        emitter.borrow().emit(&1);
+        assert_eq!(count.get(), 0);
+        emitter.borrow_mut().subscribe(|_| {});
+        assert_eq!(emitter.borrow().observers.len(), 1);
+    }
+
+    #[test]
+    fn test_mut_handlers_and_collected_results() {
+        let mut emitter: EventEmitter<u32, u32> = EventEmitter::new();
+        let mut total = 0;
+        emitter.subscribe(move |e| {
+            total += e;
+            total
+        });
+        emitter.subscribe_with_priority(1, |e| e * 10);
+        assert_eq!(emitter.emit_collect(&1), [10, 1]);
+        emitter.emit(&2);
+        assert_eq!(emitter.emit_collect(&3), [30, 6]);
+    }
+
+    #[test]
+    fn test_dispatch_stops_and_unsubscribes() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut emitter: EventEmitter<&str, Control> = EventEmitter::new();
+        for (name, priority, control) in [
+            ("once", 3, Control::Unsubscribe),
+            ("button", 2, Control::Continue),
+            ("panel", 1, Control::Stop),
+            ("window", 0, Control::Continue),
+        ] {
+            let log = Rc::clone(&log);
+            emitter.subscribe_with_priority(priority, move |e: &&str| {
+                log.borrow_mut().push(format!("{}:{}", name, e));
+                match (name, *e) {
+                    ("panel", "bubble") => Control::Continue,
+                    _ => control,
+                }
+            });
+        }
+        assert!(emitter.dispatch(&"click"));
+        assert!(!emitter.dispatch(&"bubble"));
+        assert_eq!(
+            log.borrow().join(" "),
+            "once:click button:click panel:click button:bubble panel:bubble window:bubble"
+        );
+        assert_eq!(emitter.len(), 3);
+    }
+
+    #[test]
+    fn test_reentrant_emit_skips_running_handler() {
+        let emitter: Rc<RefCell<EventEmitter<u32>>> = Rc::new(RefCell::new(EventEmitter::new()));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let weak = Rc::downgrade(&emitter);
+        let outer_log = Rc::clone(&log);
+        emitter.borrow_mut().subscribe(move |e| {
+            outer_log.borrow_mut().push(format!("outer:{}", e));
+            if *e == 0 {
+                weak.upgrade().unwrap().borrow().emit(&1);
+            }
+        });
+        let inner_log = Rc::clone(&log);
+        emitter.borrow_mut().subscribe(move |e| {
+            inner_log.borrow_mut().push(format!("inner:{}", e));
+        });
+        emitter.borrow().emit(&0);
+        assert_eq!(log.borrow().join(" "), "outer:0 inner:1 inner:0");
+    }
+
+    #[test]
+    fn test_stream_ends_when_subscription_drops() {
+        let emitter = Rc::new(RefCell::new(EventEmitter::new()));
+        let (subscription, mut events) =
+            EventEmitter::subscribe_stream(&emitter, StreamOptions::default());
+        emitter.borrow().emit(&1);
+        emitter.borrow().emit(&2);
+        drop(subscription);
+        emitter.borrow().emit(&3);
+        assert!(emitter.borrow().is_empty());
+
+        let received = stream::block_on(async {
+            let mut received = Vec::new();
+            while let Some(Ok(event)) = events.next().await {
+                received.push(event);
+            }
+            received
+        });
+        assert_eq!(received, [1, 2]);
+    }
+
+    #[test]
+    fn test_blocking_stream_drained_on_another_thread() {
+        let emitter = Rc::new(RefCell::new(EventEmitter::new()));
+        let options = StreamOptions { capacity: 1, overflow: Overflow::Block };
+        let (subscription, mut events) = EventEmitter::subscribe_stream(&emitter, options);
+        let received = std::thread::scope(|scope| {
+            let consumer = scope.spawn(move || {
+                stream::block_on(async {
+                    let mut received = Vec::new();
+                    while let Some(item) = events.next().await {
+                        received.push(item);
+                    }
+                    received
+                })
+            });
+            for i in 0..50 {
+                emitter.borrow().emit(&i);
+            }
+            drop(subscription);
+            consumer.join().unwrap()
+        });
+        assert_eq!(received, (0..50).map(Ok).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "stream capacity must be at least 1")]
+    fn test_zero_capacity_stream_subscription_panics() {
+        let emitter: Rc<RefCell<EventEmitter<u32>>> = Rc::new(RefCell::new(EventEmitter::new()));
+        let options = StreamOptions { capacity: 0, ..StreamOptions::default() };
+        let _ = EventEmitter::subscribe_stream(&emitter, options);
+    }
 }
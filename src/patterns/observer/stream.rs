@@ -0,0 +1,341 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+pub trait Stream {
+    type Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>;
+
+    fn next(&mut self) -> Next<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Next { stream: self }
+    }
+}
+
+pub struct Next<'s, S: ?Sized> {
+    stream: &'s mut S,
+}
+
+impl<S: Stream + Unpin + ?Sized> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    // `send` waits for free space, so the stream must be drained on another thread; a consumer
+    // on the sending thread (e.g. `emit` and `block_on` together) deadlocks once it fills up.
+    Block,
+    DropOldest,
+    DropNewest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamOptions {
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        StreamOptions { capacity: 16, overflow: Overflow::DropOldest }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+impl fmt::Display for Lagged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stream fell behind and missed {} events", self.0)
+    }
+}
+
+impl Error for Lagged {}
+
+// Each buffered event remembers how many events were dropped right before it, so `Lagged` is
+// reported where the gap is; `missed` counts the gap after the last buffered event.
+struct State<E> {
+    buffer: VecDeque<(u64, E)>,
+    missed: u64,
+    sender_closed: bool,
+    stream_closed: bool,
+    waker: Option<Waker>,
+}
+
+struct Shared<E> {
+    options: StreamOptions,
+    state: Mutex<State<E>>,
+    space: Condvar,
+}
+
+// Panics if `options.capacity` is 0.
+pub fn channel<E>(options: StreamOptions) -> (StreamSender<E>, EventStream<E>) {
+    assert!(options.capacity > 0, "stream capacity must be at least 1");
+    let shared = Arc::new(Shared {
+        options,
+        state: Mutex::new(State {
+            buffer: VecDeque::with_capacity(options.capacity),
+            missed: 0,
+            sender_closed: false,
+            stream_closed: false,
+            waker: None,
+        }),
+        space: Condvar::new(),
+    });
+    (StreamSender { shared: Arc::clone(&shared) }, EventStream { shared })
+}
+
+pub struct StreamSender<E> {
+    shared: Arc<Shared<E>>,
+}
+
+impl<E> StreamSender<E> {
+    pub fn send(&self, event: E) {
+        let shared = &*self.shared;
+        let mut state = shared.state.lock().unwrap();
+        if state.stream_closed {
+            return;
+        }
+        if state.buffer.len() >= shared.options.capacity {
+            match shared.options.overflow {
+                Overflow::Block => {
+                    state = shared
+                        .space
+                        .wait_while(state, |s| {
+                            s.buffer.len() >= shared.options.capacity && !s.stream_closed
+                        })
+                        .unwrap();
+                    if state.stream_closed {
+                        return;
+                    }
+                }
+                Overflow::DropOldest => {
+                    if let Some((missed, _)) = state.buffer.pop_front() {
+                        match state.buffer.front_mut() {
+                            Some((next, _)) => *next += missed + 1,
+                            None => state.missed += missed + 1,
+                        }
+                    }
+                }
+                Overflow::DropNewest => {
+                    state.missed += 1;
+                    return;
+                }
+            }
+        }
+        let missed = std::mem::take(&mut state.missed);
+        state.buffer.push_back((missed, event));
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().stream_closed
+    }
+}
+
+impl<E> Drop for StreamSender<E> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.sender_closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+pub struct EventStream<E> {
+    shared: Arc<Shared<E>>,
+}
+
+impl<E> Stream for EventStream<E> {
+    type Item = Result<E, Lagged>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some((missed, _)) = state.buffer.front_mut()
+            && *missed > 0
+        {
+            return Poll::Ready(Some(Err(Lagged(std::mem::take(missed)))));
+        }
+        if let Some((_, event)) = state.buffer.pop_front() {
+            self.shared.space.notify_one();
+            return Poll::Ready(Some(Ok(event)));
+        }
+        if state.missed > 0 {
+            let missed = std::mem::take(&mut state.missed);
+            return Poll::Ready(Some(Err(Lagged(missed))));
+        }
+        if state.sender_closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<E> Drop for EventStream<E> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().stream_closed = true;
+        self.shared.space.notify_all();
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn drain<E>(stream: &mut EventStream<E>) -> Vec<Result<E, Lagged>> {
+        block_on(async {
+            let mut items = Vec::new();
+            while let Some(item) = stream.next().await {
+                items.push(item);
+            }
+            items
+        })
+    }
+
+    #[test]
+    fn test_drop_oldest_reports_lag() {
+        let options = StreamOptions { capacity: 2, ..Default::default() };
+        let (sender, mut stream) = channel(options);
+        for i in 0..5 {
+            sender.send(i);
+        }
+        drop(sender);
+        assert_eq!(drain(&mut stream), [Err(Lagged(3)), Ok(3), Ok(4)]);
+    }
+
+    #[test]
+    fn test_drop_newest_reports_lag() {
+        let options = StreamOptions { capacity: 2, overflow: Overflow::DropNewest };
+        let (sender, mut stream) = channel(options);
+        for i in 0..5 {
+            sender.send(i);
+        }
+        drop(sender);
+        assert_eq!(drain(&mut stream), [Ok(0), Ok(1), Err(Lagged(3))]);
+    }
+
+    #[test]
+    fn test_lag_is_reported_where_events_were_dropped() {
+        let options = StreamOptions { capacity: 2, overflow: Overflow::DropNewest };
+        let (sender, mut stream) = channel(options);
+        for i in 0..4 {
+            sender.send(i);
+        }
+        let first = block_on(stream.next());
+        sender.send(4);
+        sender.send(5);
+        drop(sender);
+        assert_eq!(first, Some(Ok(0)));
+        assert_eq!(drain(&mut stream), [Ok(1), Err(Lagged(2)), Ok(4), Err(Lagged(1))]);
+
+        let options = StreamOptions { capacity: 2, ..Default::default() };
+        let (sender, mut stream) = channel(options);
+        for i in 0..3 {
+            sender.send(i);
+        }
+        let first = block_on(stream.next());
+        for i in 3..5 {
+            sender.send(i);
+        }
+        drop(sender);
+        assert_eq!(first, Some(Err(Lagged(1))));
+        assert_eq!(drain(&mut stream), [Err(Lagged(2)), Ok(3), Ok(4)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "stream capacity must be at least 1")]
+    fn test_zero_capacity_panics() {
+        channel::<u32>(StreamOptions { capacity: 0, ..Default::default() });
+    }
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_pending_stream_is_woken_by_send() {
+        let (sender, mut stream) = channel(StreamOptions::default());
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+        sender.send("a");
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Ready(Some(Ok("a"))));
+
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+        drop(sender);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 2);
+        assert_eq!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn test_block_applies_backpressure_without_losing_events() {
+        let options = StreamOptions { capacity: 3, overflow: Overflow::Block };
+        let (sender, mut stream) = channel(options);
+        let received = thread::scope(|scope| {
+            let consumer = scope.spawn(move || drain(&mut stream));
+            for i in 0..200 {
+                sender.send(i);
+            }
+            drop(sender);
+            consumer.join().unwrap()
+        });
+        let expected: Vec<Result<i32, Lagged>> = (0..200).map(Ok).collect();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_dropping_stream_unblocks_sender() {
+        let options = StreamOptions { capacity: 1, overflow: Overflow::Block };
+        let (sender, stream) = channel(options);
+        sender.send(1);
+        thread::scope(|scope| {
+            scope.spawn(move || drop(stream));
+            sender.send(2);
+        });
+        assert!(sender.is_closed());
+    }
+}
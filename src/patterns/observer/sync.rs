@@ -0,0 +1,209 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+type SyncHandler<E> = Box<dyn Fn(&E) + Send + Sync>;
+
+struct SyncObserver<E> {
+    id: u64,
+    priority: i32,
+    active: AtomicBool,
+    handler: SyncHandler<E>,
+}
+
+struct Observers<E> {
+    next_id: u64,
+    list: Vec<Arc<SyncObserver<E>>>,
+}
+
+pub struct SyncEventEmitter<E> {
+    observers: Mutex<Observers<E>>,
+}
+
+impl<E> SyncEventEmitter<E> {
+    pub fn new() -> Self {
+        SyncEventEmitter { observers: Mutex::new(Observers { next_id: 0, list: Vec::new() }) }
+    }
+
+    pub fn subscribe<F>(&self, handler: F) -> u64
+    where
+        F: Fn(&E) + Send + Sync + 'static,
+    {
+        self.subscribe_with_priority(0, handler)
+    }
+
+    pub fn subscribe_with_priority<F>(&self, priority: i32, handler: F) -> u64
+    where
+        F: Fn(&E) + Send + Sync + 'static,
+    {
+        let mut observers = self.observers.lock().unwrap();
+        let id = observers.next_id;
+        observers.next_id += 1;
+        let index = observers.list.partition_point(|o| o.priority >= priority);
+        let active = AtomicBool::new(true);
+        let observer = SyncObserver { id, priority, active, handler: Box::new(handler) };
+        observers.list.insert(index, Arc::new(observer));
+        id
+    }
+
+    pub fn unsubscribe(&self, id: u64) -> bool {
+        let mut observers = self.observers.lock().unwrap();
+        match observers.list.iter().position(|o| o.id == id) {
+            Some(index) => {
+                let observer = observers.list.remove(index);
+                observer.active.swap(false, Ordering::SeqCst)
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.lock().unwrap().list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Handlers run on a snapshot taken outside the lock, so they may subscribe or unsubscribe
+    // (themselves included) without deadlocking. Anything unsubscribed mid-emit is skipped.
+    pub fn emit(&self, event: &E) {
+        let snapshot = self.observers.lock().unwrap().list.clone();
+        for observer in snapshot {
+            if observer.active.load(Ordering::SeqCst) {
+                (observer.handler)(event);
+            }
+        }
+    }
+}
+
+impl<E> Default for SyncEventEmitter<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, AtomicUsize};
+    use std::sync::Weak;
+    use std::thread;
+
+    #[test]
+    fn test_emitter_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SyncEventEmitter<String>>();
+    }
+
+    #[test]
+    fn test_keeps_priority_and_subscription_order() {
+        let emitter = SyncEventEmitter::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for (name, priority) in [("a", 0), ("b", 5), ("c", 0), ("d", 5), ("e", -1)] {
+            let log = Arc::clone(&log);
+            emitter.subscribe_with_priority(priority, move |_: &()| log.lock().unwrap().push(name));
+        }
+        emitter.emit(&());
+        assert_eq!(*log.lock().unwrap(), ["b", "d", "a", "c", "e"]);
+    }
+
+    fn subscribe_once(emitter: &Arc<SyncEventEmitter<u64>>, calls: &Arc<AtomicUsize>) -> u64 {
+        let id = Arc::new(AtomicU64::new(u64::MAX));
+        let weak: Weak<SyncEventEmitter<u64>> = Arc::downgrade(emitter);
+        let calls = Arc::clone(calls);
+        let own_id = Arc::clone(&id);
+        let subscribed = emitter.subscribe(move |_| {
+            let emitter = weak.upgrade().unwrap();
+            if emitter.unsubscribe(own_id.load(Ordering::SeqCst)) {
+                calls.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        id.store(subscribed, Ordering::SeqCst);
+        subscribed
+    }
+
+    #[test]
+    fn test_handler_can_unsubscribe_itself_during_emit() {
+        let emitter = Arc::new(SyncEventEmitter::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        subscribe_once(&emitter, &calls);
+        let seen = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&seen);
+        emitter.subscribe(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        emitter.emit(&1);
+        emitter.emit(&2);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(seen.load(Ordering::SeqCst), 2);
+        assert_eq!(emitter.len(), 1);
+    }
+
+    #[test]
+    fn test_unsubscribed_mid_emit_handlers_are_skipped() {
+        let emitter = Arc::new(SyncEventEmitter::new());
+        let later = Arc::new(AtomicU64::new(u64::MAX));
+        let weak = Arc::downgrade(&emitter);
+        let target = Arc::clone(&later);
+        emitter.subscribe(move |_: &u64| {
+            weak.upgrade().unwrap().unsubscribe(target.load(Ordering::SeqCst));
+        });
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        later.store(
+            emitter.subscribe(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+            Ordering::SeqCst,
+        );
+        emitter.emit(&0);
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+        assert_eq!(emitter.len(), 1);
+    }
+
+    #[test]
+    fn test_stress_concurrent_subscribe_emit_unsubscribe() {
+        let emitter = Arc::new(SyncEventEmitter::new());
+        let total = Arc::new(AtomicU64::new(0));
+        let once_calls = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&total);
+        let permanent = emitter.subscribe(move |e: &u64| {
+            counter.fetch_add(*e, Ordering::SeqCst);
+        });
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for i in 1..=250 {
+                        emitter.emit(&i);
+                    }
+                });
+            }
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        let id = emitter.subscribe(|_| {});
+                        assert!(emitter.unsubscribe(id));
+                    }
+                });
+            }
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        subscribe_once(&emitter, &once_calls);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(total.load(Ordering::SeqCst), 4 * (250 * 251 / 2));
+        // Handlers subscribed while an emit was in flight may not have fired yet.
+        emitter.emit(&0);
+        assert_eq!(once_calls.load(Ordering::SeqCst), 100);
+        assert_eq!(emitter.len(), 1);
+        assert!(emitter.unsubscribe(permanent));
+        assert!(emitter.is_empty());
+    }
+}
@@ -1,29 +1,130 @@
+pub mod registry;
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+pub use oxidized_patterns_derive::Element;
+
 pub trait Visitor {
     fn visit_element_a(&mut self, element: &ElementA);
     fn visit_element_b(&mut self, element: &ElementB);
+    fn visit_composite(&mut self, _: &Composite) {}
 }
 
 pub trait Element {
     fn accept(&self, visitor: &mut dyn Visitor);
+
+    fn children(&self) -> &[Box<dyn Element>] {
+        &[]
+    }
 }
 
+pub trait OutputVisitor {
+    type Output;
+
+    fn visit_element_a(&mut self, element: &ElementA) -> Self::Output;
+    fn visit_element_b(&mut self, element: &ElementB) -> Self::Output;
+}
+
+// Opt-in for output visitors that handle composites; there is no output a composite could
+// default to for every `Output`. `visit_children` folds the visitor over a composite's children.
+pub trait CompositeVisitor: OutputVisitor {
+    fn visit_composite(&mut self, composite: &Composite) -> Self::Output;
+}
+
+pub trait Accept<V: OutputVisitor + ?Sized> {
+    fn accept_with(&self, visitor: &mut V) -> V::Output;
+}
+
+pub fn visit_all<'a, V, E>(
+    elements: impl IntoIterator<Item = &'a E>,
+    visitor: &mut V,
+) -> Vec<V::Output>
+where
+    V: OutputVisitor + ?Sized,
+    E: Accept<V> + ?Sized + 'a,
+{
+    elements.into_iter().map(|element| element.accept_with(visitor)).collect()
+}
+
+pub fn try_visit_all<'a, V, E, T, Err>(
+    elements: impl IntoIterator<Item = &'a E>,
+    visitor: &mut V,
+) -> Result<Vec<T>, Err>
+where
+    V: OutputVisitor<Output = Result<T, Err>> + ?Sized,
+    E: Accept<V> + ?Sized + 'a,
+{
+    elements.into_iter().map(|element| element.accept_with(visitor)).collect()
+}
+
+#[derive(Element)]
 pub struct ElementA {
     pub value: i32,
 }
 
-impl Element for ElementA {
+#[derive(Element)]
+pub struct ElementB {
+    pub text: String,
+}
+
+pub struct Composite {
+    pub name: String,
+    pub children: Vec<Box<dyn Element>>,
+}
+
+impl Composite {
+    pub fn new(name: impl Into<String>, children: Vec<Box<dyn Element>>) -> Self {
+        Composite { name: name.into(), children }
+    }
+}
+
+impl Element for Composite {
     fn accept(&self, visitor: &mut dyn Visitor) {
-        visitor.visit_element_a(self);
+        visitor.visit_composite(self);
+    }
+
+    fn children(&self) -> &[Box<dyn Element>] {
+        &self.children
     }
 }
 
-pub struct ElementB {
-    pub text: String,
+impl<V: CompositeVisitor + ?Sized> Accept<V> for Composite {
+    fn accept_with(&self, visitor: &mut V) -> V::Output {
+        visitor.visit_composite(self)
+    }
 }
 
-impl Element for ElementB {
-    fn accept(&self, visitor: &mut dyn Visitor) {
-        visitor.visit_element_b(self);
+// Children are `dyn Element`, which only take a `&mut dyn Visitor`, so the output visitor is
+// driven through an adapter that collects what each child returns.
+pub fn visit_children<V: CompositeVisitor + ?Sized>(
+    composite: &Composite,
+    visitor: &mut V,
+) -> Vec<V::Output> {
+    let mut collect = Collect { visitor, outputs: Vec::new() };
+    for child in &composite.children {
+        child.accept(&mut collect);
+    }
+    collect.outputs
+}
+
+struct Collect<'v, V: CompositeVisitor + ?Sized> {
+    visitor: &'v mut V,
+    outputs: Vec<V::Output>,
+}
+
+impl<V: CompositeVisitor + ?Sized> Visitor for Collect<'_, V> {
+    fn visit_element_a(&mut self, element: &ElementA) {
+        self.outputs.push(self.visitor.visit_element_a(element));
+    }
+
+    fn visit_element_b(&mut self, element: &ElementB) {
+        self.outputs.push(self.visitor.visit_element_b(element));
+    }
+
+    fn visit_composite(&mut self, composite: &Composite) {
+        self.outputs.push(self.visitor.visit_composite(composite));
     }
 }
 
@@ -38,6 +139,12 @@ impl ConcreteVisitor {
     }
 }
 
+impl Default for ConcreteVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Visitor for ConcreteVisitor {
     fn visit_element_a(&mut self, element: &ElementA) {
         self.sum += element.value;
@@ -48,6 +155,249 @@ impl Visitor for ConcreteVisitor {
     }
 }
 
+pub struct Renderer;
+
+impl OutputVisitor for Renderer {
+    type Output = String;
+
+    fn visit_element_a(&mut self, element: &ElementA) -> String {
+        format!("A({})", element.value)
+    }
+
+    fn visit_element_b(&mut self, element: &ElementB) -> String {
+        format!("B({:?})", element.text)
+    }
+}
+
+impl CompositeVisitor for Renderer {
+    fn visit_composite(&mut self, composite: &Composite) -> String {
+        let children = visit_children(composite, self);
+        format!("Composite({:?}, [{}])", composite.name, children.join(", "))
+    }
+}
+
+pub struct Size;
+
+impl OutputVisitor for Size {
+    type Output = usize;
+
+    fn visit_element_a(&mut self, _: &ElementA) -> usize {
+        std::mem::size_of::<i32>()
+    }
+
+    fn visit_element_b(&mut self, element: &ElementB) -> usize {
+        element.text.len()
+    }
+}
+
+impl CompositeVisitor for Size {
+    fn visit_composite(&mut self, composite: &Composite) -> usize {
+        composite.name.len() + visit_children(composite, self).into_iter().sum::<usize>()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    Negative(i32),
+    EmptyText,
+    EmptyName,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::Negative(value) => write!(f, "value {} is negative", value),
+            ValidationError::EmptyText => write!(f, "text is empty"),
+            ValidationError::EmptyName => write!(f, "composite name is empty"),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+pub struct Validator {
+    pub checked: usize,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Validator { checked: 0 }
+    }
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputVisitor for Validator {
+    type Output = Result<(), ValidationError>;
+
+    fn visit_element_a(&mut self, element: &ElementA) -> Self::Output {
+        self.checked += 1;
+        if element.value < 0 {
+            return Err(ValidationError::Negative(element.value));
+        }
+        Ok(())
+    }
+
+    fn visit_element_b(&mut self, element: &ElementB) -> Self::Output {
+        self.checked += 1;
+        if element.text.is_empty() {
+            return Err(ValidationError::EmptyText);
+        }
+        Ok(())
+    }
+}
+
+// Every child is checked, but the first error in document order is the one reported.
+impl CompositeVisitor for Validator {
+    fn visit_composite(&mut self, composite: &Composite) -> Self::Output {
+        self.checked += 1;
+        if composite.name.is_empty() {
+            return Err(ValidationError::EmptyName);
+        }
+        visit_children(composite, self).into_iter().collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    SkipChildren,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    PreOrder,
+    PostOrder,
+    BreadthFirst,
+}
+
+pub trait TreeVisitor {
+    fn visit_element_a(&mut self, element: &ElementA) -> Flow;
+    fn visit_element_b(&mut self, element: &ElementB) -> Flow;
+
+    fn visit_composite(&mut self, _: &Composite) -> Flow {
+        Flow::Continue
+    }
+}
+
+// Returns `Flow::Stop` if the visitor stopped the walk, `Flow::Continue` otherwise. In post-order
+// the children have already been visited, so `SkipChildren` only affects pre-order and BFS.
+pub fn walk<V: TreeVisitor + ?Sized>(root: &dyn Element, order: Order, visitor: &mut V) -> Flow {
+    let mut walker = Walker { visitor, flow: Flow::Continue };
+    match order {
+        Order::PreOrder => walker.pre_order(root),
+        Order::PostOrder => walker.post_order(root),
+        Order::BreadthFirst => walker.breadth_first(root),
+    }
+}
+
+struct Walker<'v, V: TreeVisitor + ?Sized> {
+    visitor: &'v mut V,
+    flow: Flow,
+}
+
+impl<V: TreeVisitor + ?Sized> Walker<'_, V> {
+    fn visit(&mut self, element: &dyn Element) -> Flow {
+        self.flow = Flow::Continue;
+        element.accept(self);
+        self.flow
+    }
+
+    fn pre_order(&mut self, element: &dyn Element) -> Flow {
+        match self.visit(element) {
+            Flow::Stop => return Flow::Stop,
+            Flow::SkipChildren => return Flow::Continue,
+            Flow::Continue => {}
+        }
+        for child in element.children() {
+            if self.pre_order(&**child) == Flow::Stop {
+                return Flow::Stop;
+            }
+        }
+        Flow::Continue
+    }
+
+    fn post_order(&mut self, element: &dyn Element) -> Flow {
+        for child in element.children() {
+            if self.post_order(&**child) == Flow::Stop {
+                return Flow::Stop;
+            }
+        }
+        match self.visit(element) {
+            Flow::Stop => Flow::Stop,
+            _ => Flow::Continue,
+        }
+    }
+
+    fn breadth_first(&mut self, root: &dyn Element) -> Flow {
+        let mut queue = VecDeque::from([root]);
+        while let Some(element) = queue.pop_front() {
+            match self.visit(element) {
+                Flow::Stop => return Flow::Stop,
+                Flow::SkipChildren => {}
+                Flow::Continue => queue.extend(element.children().iter().map(|c| &**c)),
+            }
+        }
+        Flow::Continue
+    }
+}
+
+impl<V: TreeVisitor + ?Sized> Visitor for Walker<'_, V> {
+    fn visit_element_a(&mut self, element: &ElementA) {
+        self.flow = self.visitor.visit_element_a(element);
+    }
+
+    fn visit_element_b(&mut self, element: &ElementB) {
+        self.flow = self.visitor.visit_element_b(element);
+    }
+
+    fn visit_composite(&mut self, composite: &Composite) {
+        self.flow = self.visitor.visit_composite(composite);
+    }
+}
+
+pub struct Outline {
+    pub labels: Vec<String>,
+    pub skip: Option<String>,
+}
+
+impl Outline {
+    pub fn new() -> Self {
+        Outline { labels: Vec::new(), skip: None }
+    }
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TreeVisitor for Outline {
+    fn visit_element_a(&mut self, element: &ElementA) -> Flow {
+        self.labels.push(element.value.to_string());
+        Flow::Continue
+    }
+
+    fn visit_element_b(&mut self, element: &ElementB) -> Flow {
+        self.labels.push(element.text.clone());
+        Flow::Continue
+    }
+
+    fn visit_composite(&mut self, composite: &Composite) -> Flow {
+        self.labels.push(composite.name.clone());
+        if self.skip.as_deref() == Some(composite.name.as_str()) {
+            return Flow::SkipChildren;
+        }
+        Flow::Continue
+    }
+}
+
 pub fn demo() {
     let elements: Vec<Box<dyn Element>> = vec![
         Box::new(ElementA { value: 2 }),
@@ -60,6 +410,50 @@ pub fn demo() {
         element.accept(&mut visitor);
     }
     println!("[Visitor demo] sum={}, texts={:?}", visitor.sum, visitor.texts);
+
+    let elements: Vec<Box<dyn Accept<Renderer>>> = vec![
+        Box::new(ElementA { value: 2 }),
+        Box::new(ElementB { text: String::from("foo") }),
+    ];
+    let rendered = visit_all(elements.iter().map(|e| &**e), &mut Renderer);
+    println!("[Visitor demo] rendered={}", rendered.join(", "));
+
+    let document = sample_document();
+    for order in [Order::PreOrder, Order::PostOrder, Order::BreadthFirst] {
+        let mut outline = Outline::new();
+        walk(&document, order, &mut outline);
+        println!("[Visitor demo] {:?}: {}", order, outline.labels.join(" "));
+    }
+
+    struct Image {
+        width: u32,
+        height: u32,
+    }
+
+    let mut registry = registry::Registry::new(|_| String::from("<unknown>"))
+        .with(|a: &ElementA| format!("A({})", a.value))
+        .with(|i: &Image| format!("Image({}x{})", i.width, i.height));
+    let image = Image { width: 640, height: 480 };
+    let text = ElementB { text: String::from("foo") };
+    let elements: [&dyn std::any::Any; 3] = [&ElementA { value: 2 }, &image, &text];
+    println!("[Visitor demo] registry={}", registry.dispatch_all(elements).join(", "));
+}
+
+fn sample_document() -> Composite {
+    Composite::new(
+        "doc",
+        vec![
+            Box::new(Composite::new(
+                "intro",
+                vec![
+                    Box::new(ElementB { text: String::from("hello") }),
+                    Box::new(ElementA { value: 1 }),
+                ],
+            )),
+            Box::new(Composite::new("body", vec![Box::new(ElementA { value: 2 })])),
+            Box::new(ElementB { text: String::from("end") }),
+        ],
+    )
 }
 
 #[cfg(test)]
@@ -79,4 +473,148 @@ mod tests {
         assert_eq!(visitor.sum, 1);
         assert_eq!(visitor.texts, vec![String::from("x")]);
     }
+
+    #[test]
+    fn test_output_visitors_return_values() {
+        let a = ElementA { value: 7 };
+        let b = ElementB { text: String::from("hello") };
+        assert_eq!(a.accept_with(&mut Renderer), "A(7)");
+        assert_eq!(b.accept_with(&mut Renderer), "B(\"hello\")");
+        assert_eq!(a.accept_with(&mut Size) + b.accept_with(&mut Size), 9);
+
+        let elements: Vec<&dyn Accept<Size>> = vec![&a, &b, &a];
+        assert_eq!(visit_all(elements, &mut Size), vec![4, 5, 4]);
+    }
+
+    #[test]
+    fn test_output_visitors_cover_composites() {
+        let document = sample_document();
+        assert_eq!(
+            document.accept_with(&mut Renderer),
+            "Composite(\"doc\", [Composite(\"intro\", [B(\"hello\"), A(1)]), \
+             Composite(\"body\", [A(2)]), B(\"end\")])"
+        );
+        assert_eq!(document.accept_with(&mut Size), 3 + 5 + 5 + 4 + 4 + 4 + 3);
+
+        let unnamed = Composite::new("", Vec::new());
+        let elements: Vec<&dyn Accept<Validator>> = vec![&document, &unnamed];
+        let mut validator = Validator::new();
+        assert_eq!(try_visit_all(elements, &mut validator), Err(ValidationError::EmptyName));
+        assert_eq!(validator.checked, 8);
+
+        let nodes = [Node::Leaf(ElementA { value: 4 }), Node::Group(unnamed)];
+        assert_eq!(visit_all(&nodes, &mut Renderer), ["A(4)", "Composite(\"\", [])"]);
+    }
+
+    #[test]
+    fn test_composite_visitors_descend_into_children() {
+        let deep = Composite::new("deep", vec![Box::new(ElementB { text: String::new() })]);
+        let nested = Composite::new(
+            "outer",
+            vec![
+                Box::new(ElementA { value: 1 }),
+                Box::new(Composite::new("", vec![Box::new(ElementA { value: -1 })])),
+                Box::new(Node::Group(deep)),
+            ],
+        );
+        let mut validator = Validator::new();
+        assert_eq!(nested.accept_with(&mut validator), Err(ValidationError::EmptyName));
+        assert_eq!(validator.checked, 5);
+
+        assert_eq!(visit_children(&nested, &mut Size), [4, 4, 4]);
+        assert_eq!(visit_children(&Composite::new("empty", Vec::new()), &mut Size), []);
+    }
+
+    #[test]
+    fn test_tree_walk_orders() {
+        let document = sample_document();
+        let outline = |order| {
+            let mut outline = Outline::new();
+            assert_eq!(walk(&document, order, &mut outline), Flow::Continue);
+            outline.labels.join(" ")
+        };
+        assert_eq!(outline(Order::PreOrder), "doc intro hello 1 body 2 end");
+        assert_eq!(outline(Order::PostOrder), "hello 1 intro 2 body end doc");
+        assert_eq!(outline(Order::BreadthFirst), "doc intro body end hello 1 2");
+    }
+
+    #[test]
+    fn test_tree_walk_skips_subtree() {
+        let document = sample_document();
+        for (order, expected) in [
+            (Order::PreOrder, "doc intro body 2 end"),
+            (Order::BreadthFirst, "doc intro body end 2"),
+            (Order::PostOrder, "hello 1 intro 2 body end doc"),
+        ] {
+            let mut outline = Outline { skip: Some(String::from("intro")), ..Outline::new() };
+            walk(&document, order, &mut outline);
+            assert_eq!(outline.labels.join(" "), expected, "{:?}", order);
+        }
+    }
+
+    struct FindFirst {
+        target: i32,
+        seen: usize,
+    }
+
+    impl TreeVisitor for FindFirst {
+        fn visit_element_a(&mut self, element: &ElementA) -> Flow {
+            self.seen += 1;
+            if element.value == self.target { Flow::Stop } else { Flow::Continue }
+        }
+
+        fn visit_element_b(&mut self, _: &ElementB) -> Flow {
+            self.seen += 1;
+            Flow::Continue
+        }
+    }
+
+    #[test]
+    fn test_tree_walk_stops() {
+        let document = sample_document();
+        let orders = [(Order::PreOrder, 2), (Order::PostOrder, 2), (Order::BreadthFirst, 3)];
+        for (order, seen) in orders {
+            let mut finder = FindFirst { target: 1, seen: 0 };
+            assert_eq!(walk(&document, order, &mut finder), Flow::Stop);
+            assert_eq!(finder.seen, seen, "{:?}", order);
+        }
+        let mut finder = FindFirst { target: 9, seen: 0 };
+        assert_eq!(walk(&document, Order::PreOrder, &mut finder), Flow::Continue);
+        assert_eq!(finder.seen, 4);
+    }
+
+    #[derive(Element)]
+    enum Node {
+        Leaf(ElementA),
+        Group(Composite),
+    }
+
+    #[test]
+    fn test_derived_enum_forwards_children() {
+        let group = Node::Group(Composite::new("g", vec![Box::new(ElementA { value: 3 })]));
+        let leaf = Node::Leaf(ElementA { value: 4 });
+        assert_eq!(group.children().len(), 1);
+        assert!(leaf.children().is_empty());
+
+        let mut outline = Outline::new();
+        walk(&group, Order::PreOrder, &mut outline);
+        assert_eq!(outline.labels, ["g", "3"]);
+    }
+
+    #[test]
+    fn test_fallible_visitor_stops_at_first_error() {
+        let ok = ElementA { value: 1 };
+        let negative = ElementA { value: -3 };
+        let empty = ElementB { text: String::new() };
+
+        let elements: Vec<&dyn Accept<Validator>> = vec![&ok, &negative, &empty, &ok];
+        let mut validator = Validator::new();
+        let result = try_visit_all(elements, &mut validator);
+        assert_eq!(result, Err(ValidationError::Negative(-3)));
+        assert_eq!(validator.checked, 2);
+
+        let elements: Vec<&dyn Accept<Validator>> = vec![&ok, &ok];
+        let mut validator = Validator::new();
+        assert_eq!(try_visit_all(elements, &mut validator), Ok(vec![(), ()]));
+    }
 }
@@ -0,0 +1,221 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use super::{Composite, Element, ElementA, ElementB, Visitor};
+
+type Handler<'h, R> = Box<dyn FnMut(&dyn Any) -> R + 'h>;
+
+pub struct Registry<'h, R> {
+    handlers: HashMap<TypeId, Handler<'h, R>>,
+    fallback: Handler<'h, R>,
+}
+
+impl<'h, R> Registry<'h, R> {
+    pub fn new(fallback: impl FnMut(&dyn Any) -> R + 'h) -> Self {
+        Registry { handlers: HashMap::new(), fallback: Box::new(fallback) }
+    }
+
+    pub fn with<T: Any>(mut self, handler: impl FnMut(&T) -> R + 'h) -> Self {
+        self.register(handler);
+        self
+    }
+
+    pub fn register<T: Any>(&mut self, mut handler: impl FnMut(&T) -> R + 'h) -> bool {
+        let handler: Handler<'h, R> = Box::new(move |element: &dyn Any| {
+            handler(element.downcast_ref::<T>().expect("handler registered under wrong TypeId"))
+        });
+        self.handlers.insert(TypeId::of::<T>(), handler).is_some()
+    }
+
+    pub fn unregister<T: Any>(&mut self) -> bool {
+        self.handlers.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    pub fn handles<T: Any>(&self) -> bool {
+        self.handlers.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    // Keys on the concrete type of `value` itself: a `&Box<dyn Element>` is a `Box` and lands in
+    // the fallback. Elements, boxed or not, go through `dispatch`.
+    pub fn dispatch_any(&mut self, value: &dyn Any) -> R {
+        match self.handlers.get_mut(&value.type_id()) {
+            Some(handler) => handler(value),
+            None => (self.fallback)(value),
+        }
+    }
+
+    pub fn dispatch_all<'a>(&mut self, values: impl IntoIterator<Item = &'a dyn Any>) -> Vec<R> {
+        values.into_iter().map(|value| self.dispatch_any(value)).collect()
+    }
+
+    // Keys on the type `element` visits as, so trait objects and elements that aren't `'static`
+    // work too. An element whose `accept` visits nothing reaches the fallback as `&()`.
+    pub fn dispatch<T: Element + ?Sized>(&mut self, element: &T) -> R {
+        let mut dispatch = Dispatch { registry: self, result: None };
+        element.accept(&mut dispatch);
+        match dispatch.result {
+            Some(result) => result,
+            None => (self.fallback)(&()),
+        }
+    }
+
+    // Pre-order over `root` and its descendants.
+    pub fn dispatch_tree(&mut self, root: &dyn Element) -> Vec<R> {
+        let mut results = Vec::new();
+        let mut stack = vec![root];
+        while let Some(element) = stack.pop() {
+            results.push(self.dispatch(element));
+            stack.extend(element.children().iter().rev().map(|child| &**child));
+        }
+        results
+    }
+}
+
+struct Dispatch<'r, 'h, R> {
+    registry: &'r mut Registry<'h, R>,
+    result: Option<R>,
+}
+
+impl<R> Dispatch<'_, '_, R> {
+    fn dispatch(&mut self, element: &dyn Any) {
+        if self.result.is_none() {
+            self.result = Some(self.registry.dispatch_any(element));
+        }
+    }
+}
+
+impl<R> Visitor for Dispatch<'_, '_, R> {
+    fn visit_element_a(&mut self, element: &ElementA) {
+        self.dispatch(element);
+    }
+
+    fn visit_element_b(&mut self, element: &ElementB) {
+        self.dispatch(element);
+    }
+
+    fn visit_composite(&mut self, composite: &Composite) {
+        self.dispatch(composite);
+    }
+}
+
+impl<R: Default> Default for Registry<'_, R> {
+    fn default() -> Self {
+        Registry::new(|_| R::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patterns::visitor::{Composite, ElementA, ElementB};
+
+    struct Chart {
+        points: Vec<i32>,
+    }
+
+    struct Unknown;
+
+    fn render() -> Registry<'static, String> {
+        Registry::new(|_| String::from("?"))
+            .with(|a: &ElementA| format!("A({})", a.value))
+            .with(|b: &ElementB| format!("B({})", b.text))
+    }
+
+    #[test]
+    fn test_dispatches_by_type_and_falls_back() {
+        let mut registry = render();
+        let a = ElementA { value: 1 };
+        let b = ElementB { text: String::from("x") };
+        let elements: [&dyn Any; 3] = [&a, &Unknown, &b];
+        assert_eq!(registry.dispatch_all(elements), ["A(1)", "?", "B(x)"]);
+    }
+
+    #[test]
+    fn test_plugins_register_new_types_at_runtime() {
+        let mut registry = render();
+        let chart = Chart { points: vec![1, 2, 3] };
+        assert!(!registry.handles::<Chart>());
+        assert_eq!(registry.dispatch_any(&chart), "?");
+
+        assert!(!registry.register(|c: &Chart| format!("Chart({} points)", c.points.len())));
+        assert!(registry.handles::<Chart>());
+        assert_eq!(registry.len(), 3);
+        assert_eq!(registry.dispatch_any(&chart), "Chart(3 points)");
+
+        assert!(registry.register(|_: &Chart| String::from("chart")));
+        assert_eq!(registry.dispatch_any(&chart), "chart");
+        assert!(registry.unregister::<Chart>());
+        assert_eq!(registry.dispatch_any(&chart), "?");
+    }
+
+    #[test]
+    fn test_handlers_can_borrow_state() {
+        let mut sum = 0;
+        let mut unhandled = 0;
+        {
+            let mut registry = Registry::new(|_| unhandled += 1)
+                .with(|a: &ElementA| sum += a.value);
+            for element in [&ElementA { value: 2 } as &dyn Any, &Unknown, &ElementA { value: 5 }] {
+                registry.dispatch_any(element);
+            }
+        }
+        assert_eq!((sum, unhandled), (7, 1));
+
+        let mut counts: Registry<usize> = Registry::default().with(|b: &ElementB| b.text.len());
+        assert_eq!(counts.dispatch(&ElementB { text: String::from("four") }), 4);
+        assert_eq!(counts.dispatch_any(&Unknown), 0);
+    }
+
+    #[test]
+    fn test_dispatches_over_element_trees() {
+        let mut registry = render().with(|c: &Composite| format!("[{}]", c.name));
+        let document = Composite::new(
+            "doc",
+            vec![
+                Box::new(ElementA { value: 1 }),
+                Box::new(Composite::new("inner", vec![Box::new(ElementB { text: "y".into() })])),
+                Box::new(ElementA { value: 2 }),
+            ],
+        );
+        assert_eq!(registry.dispatch_tree(&document), ["[doc]", "A(1)", "[inner]", "B(y)", "A(2)"]);
+
+        // A boxed child is a `Box` to `dispatch_any`; `dispatch` sees through it.
+        let boxed = &document.children[0];
+        assert_eq!(registry.dispatch_any(boxed), "?");
+        assert_eq!(registry.dispatch(&**boxed), "A(1)");
+        assert_eq!(registry.dispatch(boxed.as_ref()), "A(1)");
+    }
+
+    struct Borrowed<'a>(&'a str);
+
+    impl Element for Borrowed<'_> {
+        fn accept(&self, visitor: &mut dyn Visitor) {
+            visitor.visit_element_b(&ElementB { text: self.0.to_uppercase() });
+        }
+    }
+
+    struct Silent;
+
+    impl Element for Silent {
+        fn accept(&self, _: &mut dyn Visitor) {}
+    }
+
+    #[test]
+    fn test_dispatches_borrowed_and_silent_elements() {
+        let text = String::from("x");
+        let mut registry = render();
+        assert_eq!(registry.dispatch(&Borrowed(&text)), "B(X)");
+
+        let mut fallback = Registry::new(|value| value.is::<()>()).with(|_: &ElementA| false);
+        assert!(fallback.dispatch(&Silent));
+        assert!(!fallback.dispatch(&ElementA { value: 1 }));
+    }
+}